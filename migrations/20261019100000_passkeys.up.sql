@@ -0,0 +1,13 @@
+CREATE TABLE passkeys (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    credential_id VARCHAR(1024) NOT NULL UNIQUE,
+    name VARCHAR(50) NOT NULL DEFAULT '',
+    -- serialized webauthn-rs Passkey, including the public key and sign counter
+    passkey TEXT NOT NULL,
+    last_used_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX passkeys_user_id_idx ON passkeys (user_id);
@@ -0,0 +1,8 @@
+-- accounts waiting out the deletion grace period, logging in cancels the request
+CREATE TABLE account_deletions (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    scheduled_for TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX account_deletions_scheduled_for_idx ON account_deletions (scheduled_for);
@@ -0,0 +1,5 @@
+ALTER TABLE profiles
+    DROP COLUMN IF EXISTS display_name,
+    DROP COLUMN IF EXISTS website,
+    DROP COLUMN IF EXISTS location,
+    DROP COLUMN IF EXISTS pronouns;
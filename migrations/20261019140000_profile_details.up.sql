@@ -0,0 +1,5 @@
+ALTER TABLE profiles
+    ADD COLUMN display_name VARCHAR(50) NOT NULL DEFAULT '',
+    ADD COLUMN website VARCHAR(100) NOT NULL DEFAULT '',
+    ADD COLUMN location VARCHAR(50) NOT NULL DEFAULT '',
+    ADD COLUMN pronouns VARCHAR(30) NOT NULL DEFAULT '';
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS comments_user_id_created_at_idx;
+DROP INDEX IF EXISTS posts_user_id_created_at_idx;
+DROP INDEX IF EXISTS user_username_trgm_idx;
+ALTER TABLE users DROP COLUMN IF EXISTS suspended_at;
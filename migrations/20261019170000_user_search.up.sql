@@ -0,0 +1,8 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- set by moderators, suspended accounts cannot log in and are hidden from the directory
+ALTER TABLE users ADD COLUMN suspended_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX user_username_trgm_idx ON users USING GIN (username gin_trgm_ops);
+CREATE INDEX posts_user_id_created_at_idx ON posts (user_id, created_at);
+CREATE INDEX comments_user_id_created_at_idx ON comments (user_id, created_at);
@@ -0,0 +1,13 @@
+-- change log of comments so comment streams can resume after a reconnect
+CREATE TABLE comment_events (
+    id BIGSERIAL PRIMARY KEY,
+    post_id UUID NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    -- no foreign key, the event outlives a deleted comment
+    comment_id UUID NOT NULL,
+    user_id UUID NOT NULL,
+    action VARCHAR(10) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX comment_events_post_id_id_idx ON comment_events (post_id, id);
+CREATE INDEX comment_events_created_at_idx ON comment_events (created_at);
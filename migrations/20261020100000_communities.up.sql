@@ -0,0 +1,22 @@
+CREATE TABLE communities (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name VARCHAR(30) NOT NULL UNIQUE,
+    description TEXT NOT NULL DEFAULT '',
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL
+);
+
+CREATE TABLE community_members (
+    community_id UUID NOT NULL REFERENCES communities(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- owner, moderator, member or banned
+    role VARCHAR(20) NOT NULL DEFAULT 'member',
+    joined_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+    PRIMARY KEY (community_id, user_id)
+);
+
+CREATE INDEX community_members_user_id_idx ON community_members (user_id);
+
+ALTER TABLE posts ADD COLUMN community_id UUID REFERENCES communities(id) ON DELETE CASCADE;
+CREATE INDEX posts_community_id_created_at_idx ON posts (community_id, created_at);
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS posts_quote_of_id_idx;
+DROP INDEX IF EXISTS posts_repost_of_id_idx;
+DROP INDEX IF EXISTS posts_user_id_repost_of_id_idx;
+ALTER TABLE posts DROP COLUMN IF EXISTS quote_of_id, DROP COLUMN IF EXISTS repost_of_id;
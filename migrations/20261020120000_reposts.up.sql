@@ -0,0 +1,9 @@
+-- a plain repost is an empty post pointing at the original and disappears with it,
+-- a quote post keeps its own content when the quoted post is deleted
+ALTER TABLE posts
+    ADD COLUMN repost_of_id UUID REFERENCES posts(id) ON DELETE CASCADE,
+    ADD COLUMN quote_of_id UUID REFERENCES posts(id) ON DELETE SET NULL;
+
+CREATE UNIQUE INDEX posts_user_id_repost_of_id_idx ON posts (user_id, repost_of_id) WHERE repost_of_id IS NOT NULL;
+CREATE INDEX posts_repost_of_id_idx ON posts (repost_of_id) WHERE repost_of_id IS NOT NULL;
+CREATE INDEX posts_quote_of_id_idx ON posts (quote_of_id) WHERE quote_of_id IS NOT NULL;
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS posts_user_id_pinned_at_idx;
+ALTER TABLE posts DROP COLUMN IF EXISTS pinned_at;
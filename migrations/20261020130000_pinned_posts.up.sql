@@ -0,0 +1,2 @@
+ALTER TABLE posts ADD COLUMN pinned_at TIMESTAMP WITH TIME ZONE;
+CREATE INDEX posts_user_id_pinned_at_idx ON posts (user_id, pinned_at) WHERE pinned_at IS NOT NULL;
@@ -0,0 +1,7 @@
+-- draft, scheduled or published
+ALTER TABLE posts
+    ADD COLUMN status VARCHAR(20) NOT NULL DEFAULT 'published',
+    ADD COLUMN publish_at TIMESTAMP WITH TIME ZONE;
+
+CREATE INDEX posts_scheduled_publish_at_idx ON posts (publish_at) WHERE status = 'scheduled';
+CREATE INDEX posts_user_id_unpublished_idx ON posts (user_id) WHERE status <> 'published';
@@ -0,0 +1,3 @@
+-- accounts created through social login have no password until the user sets one
+ALTER TABLE users ALTER COLUMN password DROP NOT NULL;
+UPDATE users SET password = NULL WHERE password = '';
@@ -0,0 +1,2 @@
+-- sessions remember the epoch they started in, bumping it ends all of them
+ALTER TABLE users ADD COLUMN session_epoch INTEGER NOT NULL DEFAULT 0;
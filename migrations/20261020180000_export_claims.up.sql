@@ -0,0 +1,2 @@
+-- exports stuck in processing after a crash are claimed again once this is old
+ALTER TABLE data_exports ADD COLUMN claimed_at TIMESTAMP WITH TIME ZONE;
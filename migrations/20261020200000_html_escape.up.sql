@@ -0,0 +1,11 @@
+-- search highlights are HTML, the text around the <b></b> tags must be escaped
+CREATE FUNCTION html_escape(input TEXT) RETURNS TEXT
+LANGUAGE SQL IMMUTABLE STRICT
+AS $$
+    SELECT replace(replace(replace(replace(replace(input,
+        '&', '&amp;'),
+        '<', '&lt;'),
+        '>', '&gt;'),
+        '"', '&quot;'),
+        '''', '&#39;')
+$$;
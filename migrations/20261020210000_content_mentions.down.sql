@@ -0,0 +1 @@
+DROP FUNCTION IF EXISTS content_with_mentions(TEXT, UUID, UUID);
@@ -0,0 +1,14 @@
+-- content together with the usernames recorded as mentioned in it, only those
+-- are linked when the content is rendered as HTML
+CREATE FUNCTION content_with_mentions(content TEXT, post UUID, comment UUID) RETURNS JSON
+LANGUAGE SQL STABLE
+AS $$
+    SELECT json_build_object(
+        'content', content,
+        'mentions', ARRAY(
+            SELECT users.username FROM mentions
+            JOIN users ON users.id = mentions.user_id
+            WHERE mentions.post_id = post AND mentions.comment_id IS NOT DISTINCT FROM comment
+        )
+    )
+$$;
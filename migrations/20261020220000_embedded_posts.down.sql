@@ -0,0 +1,4 @@
+DROP FUNCTION IF EXISTS quote_count(UUID, UUID);
+DROP FUNCTION IF EXISTS repost_count(UUID, UUID);
+DROP FUNCTION IF EXISTS embedded_post(UUID, UUID);
+DROP FUNCTION IF EXISTS can_view_posts_of(UUID, UUID);
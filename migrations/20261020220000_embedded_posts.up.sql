@@ -0,0 +1,54 @@
+-- the visibility rule shared by embedded posts and share counts: no block in
+-- either direction, and private accounts only for themselves and their followers
+CREATE FUNCTION can_view_posts_of(author UUID, viewer UUID) RETURNS BOOLEAN
+LANGUAGE SQL STABLE
+AS $$
+    SELECT NOT EXISTS (
+            SELECT 1 FROM blocks
+            WHERE (blocker_id = viewer AND blocked_id = author)
+                OR (blocker_id = author AND blocked_id = viewer)
+        )
+        AND (author = viewer IS TRUE
+            OR NOT COALESCE((SELECT is_private FROM profiles WHERE user_id = author), FALSE)
+            OR EXISTS (SELECT 1 FROM follows WHERE follower_id = viewer AND followee_id = author))
+$$;
+
+-- the reposted or quoted post as embedded in post responses, NULL when it was
+-- deleted, is not published or the viewer may not see it
+CREATE FUNCTION embedded_post(original UUID, viewer UUID) RETURNS JSON
+LANGUAGE SQL STABLE
+AS $$
+    SELECT json_build_object(
+            'id', originals.id,
+            'user_id', originals.user_id,
+            'username', original_users.username,
+            'display_name', original_profiles.display_name,
+            'profile_image', original_profiles.profile_image,
+            'title', originals.title,
+            'content', originals.content,
+            'content_html', content_with_mentions(originals.content, originals.id, NULL),
+            'created_at', originals.created_at)
+    FROM posts AS originals
+    JOIN users AS original_users ON original_users.id = originals.user_id
+    JOIN profiles AS original_profiles ON original_profiles.user_id = originals.user_id
+    WHERE originals.id = original
+        AND originals.status = 'published'
+        AND can_view_posts_of(originals.user_id, viewer)
+$$;
+
+-- reposts and published quotes of a post that the viewer may see
+CREATE FUNCTION repost_count(post UUID, viewer UUID) RETURNS BIGINT
+LANGUAGE SQL STABLE
+AS $$
+    SELECT COUNT(*) FROM posts AS reposts
+    WHERE reposts.repost_of_id = post AND can_view_posts_of(reposts.user_id, viewer)
+$$;
+
+CREATE FUNCTION quote_count(post UUID, viewer UUID) RETURNS BIGINT
+LANGUAGE SQL STABLE
+AS $$
+    SELECT COUNT(*) FROM posts AS quotes
+    WHERE quotes.quote_of_id = post
+        AND quotes.status = 'published'
+        AND can_view_posts_of(quotes.user_id, viewer)
+$$;
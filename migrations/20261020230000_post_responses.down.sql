@@ -0,0 +1 @@
+DROP FUNCTION IF EXISTS post_response(UUID, UUID);
@@ -0,0 +1,40 @@
+-- a post as returned by the post endpoints: the post, its author's profile,
+-- reaction counts and what the viewer may see of its reposts and quotes.
+-- Which posts are listed is up to the endpoint, using can_view_posts_of
+CREATE FUNCTION post_response(post UUID, viewer UUID) RETURNS JSON
+LANGUAGE SQL STABLE
+AS $$
+    SELECT json_build_object(
+            'id', posts.id,
+            'user_id', posts.user_id,
+            'community_id', posts.community_id,
+            'repost_of_id', posts.repost_of_id,
+            'quote_of_id', posts.quote_of_id,
+            'pinned_at', posts.pinned_at,
+            'status', posts.status,
+            'publish_at', posts.publish_at,
+            'original', embedded_post(COALESCE(posts.repost_of_id, posts.quote_of_id), viewer),
+            'repost_count', repost_count(posts.id, viewer),
+            'quote_count', quote_count(posts.id, viewer),
+            'username', users.username,
+            'display_name', profiles.display_name,
+            'pronouns', profiles.pronouns,
+            'bio', profiles.bio,
+            'website', profiles.website,
+            'location', profiles.location,
+            'profile_image', profiles.profile_image,
+            'title', posts.title,
+            'content', posts.content,
+            'content_html', content_with_mentions(posts.content, posts.id, NULL),
+            'likes', (SELECT COUNT(*) FROM reactions WHERE reactions.post_id = posts.id AND reactions.reaction_type = TRUE),
+            'dislikes', (SELECT COUNT(*) FROM reactions WHERE reactions.post_id = posts.id AND reactions.reaction_type = FALSE),
+            'is_bookmarked', CASE WHEN viewer IS NULL THEN NULL
+                ELSE EXISTS (SELECT 1 FROM bookmarks WHERE bookmarks.user_id = viewer AND bookmarks.post_id = posts.id)
+            END,
+            'updated_at', posts.updated_at,
+            'created_at', posts.created_at)
+    FROM posts
+    JOIN users ON users.id = posts.user_id
+    JOIN profiles ON profiles.user_id = posts.user_id
+    WHERE posts.id = post
+$$;
@@ -0,0 +1,2 @@
+-- failed attempts of the background job to publish a scheduled post
+ALTER TABLE posts ADD COLUMN publish_attempts INTEGER NOT NULL DEFAULT 0;
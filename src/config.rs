@@ -1,11 +1,52 @@
 #[derive(Debug,Clone)]
 pub struct Config {
     pub database_url: String,
+    pub oidc: Option<OidcConfig>,
+    pub mail: Option<MailConfig>,
+    pub webauthn_rp_id: String,
+    pub webauthn_rp_origin: String,
+    pub password_policy: PasswordPolicyConfig,
+    pub argon2: Argon2Config,
+    pub account_deletion_grace_days: i32,
+    pub data_export_ttl_hours: i32,
+    pub feed_popular_threshold: i64,
+    pub trending_tags_window_hours: i32,
+    pub max_pinned_posts: i64,
     //pub jwt_secret: String,
     //pub jwt_expires_in: String,
     //pub jwt_maxage: i32,
 }
 
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    pub scopes: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MailConfig {
+    pub smtp_url: String,
+    pub from: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordPolicyConfig {
+    pub min_length: usize,
+    pub min_score: u8,
+    pub min_character_classes: usize,
+    pub breached_list_path: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Argon2Config {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
 impl Config {
     pub fn init() -> Config {
         let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
@@ -14,9 +55,81 @@ impl Config {
         //let jwt_maxage = std::env::var("JWT_MAXAGE").expect("JWT_MAXAGE must be set");
         Config {
             database_url,
+            oidc: OidcConfig::init(),
+            mail: MailConfig::init(),
+            webauthn_rp_id: std::env::var("WEBAUTHN_RP_ID")
+                .unwrap_or_else(|_| "localhost".to_string()),
+            webauthn_rp_origin: std::env::var("WEBAUTHN_RP_ORIGIN")
+                .unwrap_or_else(|_| "http://localhost:5173".to_string()),
+            password_policy: PasswordPolicyConfig::init(),
+            argon2: Argon2Config::init(),
+            account_deletion_grace_days: env_or("ACCOUNT_DELETION_GRACE_DAYS", 14),
+            data_export_ttl_hours: env_or("DATA_EXPORT_TTL_HOURS", 48),
+            feed_popular_threshold: env_or("FEED_POPULAR_THRESHOLD", 10),
+            trending_tags_window_hours: env_or("TRENDING_TAGS_WINDOW_HOURS", 24),
+            max_pinned_posts: env_or("MAX_PINNED_POSTS", 3),
             //jwt_secret,
             //jwt_expires_in,
             //sjwt_maxage: jwt_maxage.parse::<i32>().unwrap(),
         }
     }
 }
+
+impl OidcConfig {
+    // social login is only enabled when an issuer is configured
+    fn init() -> Option<OidcConfig> {
+        let issuer_url = std::env::var("OIDC_ISSUER_URL").ok()?;
+        let client_id = std::env::var("OIDC_CLIENT_ID").expect("OIDC_CLIENT_ID must be set");
+        let redirect_url =
+            std::env::var("OIDC_REDIRECT_URL").expect("OIDC_REDIRECT_URL must be set");
+        Some(OidcConfig {
+            issuer_url: issuer_url.trim_end_matches('/').to_string(),
+            client_id,
+            client_secret: std::env::var("OIDC_CLIENT_SECRET").ok(),
+            redirect_url,
+            scopes: std::env::var("OIDC_SCOPES")
+                .unwrap_or_else(|_| "openid email profile".to_string()),
+        })
+    }
+}
+
+impl MailConfig {
+    // password reset emails are only sent when a mail server is configured
+    fn init() -> Option<MailConfig> {
+        let smtp_url = std::env::var("SMTP_URL").ok()?;
+        let from = std::env::var("MAIL_FROM").expect("MAIL_FROM must be set");
+        Some(MailConfig { smtp_url, from })
+    }
+}
+
+impl PasswordPolicyConfig {
+    fn init() -> PasswordPolicyConfig {
+        PasswordPolicyConfig {
+            min_length: env_or("PASSWORD_MIN_LENGTH", 8),
+            min_score: env_or("PASSWORD_MIN_SCORE", 2),
+            min_character_classes: env_or("PASSWORD_MIN_CHARACTER_CLASSES", 2),
+            breached_list_path: std::env::var("PASSWORD_BREACHED_LIST")
+                .unwrap_or_else(|_| "./breached_passwords.txt".to_string()),
+        }
+    }
+}
+
+impl Argon2Config {
+    // defaults match the OWASP recommendation used by Argon2::default()
+    fn init() -> Argon2Config {
+        Argon2Config {
+            memory_kib: env_or("ARGON2_MEMORY_KIB", 19 * 1024),
+            iterations: env_or("ARGON2_ITERATIONS", 2),
+            parallelism: env_or("ARGON2_PARALLELISM", 1),
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    match std::env::var(key) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a valid number", key)),
+        Err(_) => default,
+    }
+}
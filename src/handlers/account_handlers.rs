@@ -0,0 +1,176 @@
+use crate::{
+    handlers::auth_handlers::confirm_identity,
+    jobs::EXPORTS_DIR,
+    model::{DataExportResponse, UserModel},
+    realtime::RealtimeEvent,
+    response::{AppError, AppJson, AppPath, JsendResponse},
+    schema::DeleteAccountSchema,
+    tokens::{hash_token, random_token},
+    AppState,
+};
+use axum::{
+    extract::State,
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::IntoResponse,
+    Extension, Json,
+};
+use serde_json::json;
+use std::{path::PathBuf, sync::Arc};
+use tower_sessions::Session;
+
+pub async fn delete_account_handler(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppJson(body): AppJson<DeleteAccountSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    confirm_identity(&session, &data, &user, &body.password, "password").await?;
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let scheduled_for = sqlx::query_scalar!(
+        "INSERT INTO account_deletions (user_id, scheduled_for)
+        VALUES ($1, NOW() + make_interval(days => $2))
+        ON CONFLICT (user_id) DO UPDATE SET scheduled_for = EXCLUDED.scheduled_for
+        RETURNING scheduled_for",
+        user.id,
+        data.env.account_deletion_grace_days
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    // ends every other session for good, logging in again cancels the deletion
+    // but only the new session is valid
+    let session_epoch = sqlx::query_scalar!(
+        "UPDATE users SET session_epoch = session_epoch + 1 WHERE id = $1 RETURNING session_epoch",
+        user.id
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    // open WebSocket and event stream connections end with the sessions
+    let user_id = user.id.ok_or(AppError::InternalServerError)?;
+    data.realtime
+        .publish(RealtimeEvent::SessionsEnded {
+            user_id,
+            session_epoch,
+        })
+        .await;
+
+    session
+        .delete()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "scheduled_for" : scheduled_for
+    })));
+    Ok(Json(response))
+}
+
+pub async fn request_export_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+) -> Result<impl IntoResponse, AppError> {
+    let in_progress: bool = sqlx::query_scalar!(
+        "SELECT EXISTS (SELECT 1 FROM data_exports WHERE user_id = $1 AND status IN ('pending', 'processing'))",
+        user.id
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .unwrap_or(false);
+
+    if in_progress {
+        return Err(AppError::JsendFail(
+            json!({"export" : "an export is already being prepared"}),
+        ));
+    }
+
+    // the archive is built by the export job, the link works once it is ready
+    let token = random_token();
+    let export_id = sqlx::query_scalar!(
+        "INSERT INTO data_exports (user_id, token_hash) VALUES ($1, $2) RETURNING id",
+        user.id,
+        hash_token(&token)
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "export_id" : export_id,
+        "download_url" : format!("/exports/{}", token),
+    })));
+    Ok(Json(response))
+}
+
+pub async fn get_exports_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+) -> Result<impl IntoResponse, AppError> {
+    let exports = sqlx::query_as!(
+        DataExportResponse,
+        "SELECT id, status, expires_at, completed_at, created_at FROM data_exports
+        WHERE user_id = $1 ORDER BY created_at DESC",
+        user.id
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "exports" : exports
+    })));
+    Ok(Json(response))
+}
+
+pub async fn download_export_handler(
+    State(data): State<Arc<AppState>>,
+    AppPath(token): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let export = sqlx::query!(
+        "SELECT status, file_name FROM data_exports
+        WHERE token_hash = $1 AND (expires_at IS NULL OR expires_at > NOW())",
+        hash_token(&token)
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or_else(|| AppError::JsendFail(json!({"export" : "link is invalid or expired"})))?;
+
+    let file_name = match (export.status.as_str(), export.file_name) {
+        ("ready", Some(file_name)) => file_name,
+        ("failed", _) => return Err(AppError::JsendError("export failed".to_string())),
+        _ => {
+            return Err(AppError::JsendFail(
+                json!({"export" : "export is not ready yet"}),
+            ))
+        }
+    };
+
+    let bytes = tokio::fs::read(PathBuf::from(EXPORTS_DIR).join(&file_name))
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, "application/zip".to_string()),
+            (
+                CONTENT_DISPOSITION,
+                "attachment; filename=\"blaze-export.zip\"".to_string(),
+            ),
+        ],
+        bytes,
+    ))
+}
@@ -1,17 +1,19 @@
 use crate::{
     model::UserModel,
     response::{AppError, AppJson, JsendResponse},
-    schema::{LoginUserSchema, RegisterUserSchema},
+    schema::{
+        ChangePasswordSchema, ForgotPasswordSchema, LoginUserSchema, RegisterUserSchema,
+        ResetPasswordSchema,
+    },
+    tokens::{hash_token, random_token},
+    session_auth::SESSION_EPOCH,
     AppState,
 };
 
-use argon2::{
-    password_hash::{rand_core::OsRng, SaltString},
-    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
-};
-
 use axum::{extract::State, response::IntoResponse, Extension, Json};
+use chrono::Utc;
 use serde_json::json;
+use sqlx::{Postgres, Transaction};
 use std::{collections::HashMap, sync::Arc};
 use tower_sessions::Session;
 use uuid::Uuid;
@@ -33,23 +35,33 @@ pub async fn login_handler(
     .map_err(|_| AppError::InternalServerError)?
     .ok_or_else(|| AppError::JsendFail(json!({"username" : "user does not exist"})))?;
 
-    let is_valid = match PasswordHash::new(&user.password) {
-        Ok(parsed_hash) => Argon2::default()
-            .verify_password(body.password.as_bytes(), &parsed_hash)
-            .map_or(false, |_| true),
-        Err(_) => false,
+    let Some(password_hash) = &user.password else {
+        return Err(AppError::JsendFail(
+            json!({"password" : "this account has no password, log in with your identity provider"}),
+        ));
     };
-
-    if !is_valid {
+    if !data.hashing.verify(&body.password, password_hash) {
         return Err(AppError::JsendFail(
             json!({"password" : "password is incorrect"}),
         ));
     }
 
-    session
-        .insert("user_id", user.id)
+    // migrate hashes made with older parameters while the plain password is at hand
+    if data.hashing.needs_rehash(password_hash) {
+        let rehashed = data.hashing.hash(&body.password)?;
+        if let Err(err) = sqlx::query!(
+            "UPDATE users SET password = $1 WHERE id = $2",
+            rehashed,
+            user.id
+        )
+        .execute(&data.db)
         .await
-        .map_err(|_| AppError::InternalServerError)?;
+        {
+            tracing::warn!("failed to upgrade password hash: {}", err);
+        }
+    }
+
+    start_session(&session, &data, user.id).await?;
 
     let response = JsendResponse::success(Some(json!({
         "username" : body.username
@@ -71,6 +83,7 @@ pub async fn register_handler(
     AppJson(body): AppJson<RegisterUserSchema>,
 ) -> Result<impl IntoResponse, AppError> {
     body.validate()?;
+    let email = body.email.to_ascii_lowercase();
     let user_exists: bool = sqlx::query_scalar!(
         "SELECT EXISTS (SELECT 1 FROM users WHERE username = $1)",
         body.username.to_owned()
@@ -82,7 +95,7 @@ pub async fn register_handler(
 
     let email_exists: bool = sqlx::query_scalar!(
         "SELECT EXISTS (SELECT 1 FROM users WHERE email = $1)",
-        body.email
+        email
     )
     .fetch_one(&data.db)
     .await
@@ -103,35 +116,23 @@ pub async fn register_handler(
         return Err(AppError::JsendFail(json!(fails)));
     }
 
-    let salt = SaltString::generate(&mut OsRng);
-    let hashed_password = Argon2::default()
-        .hash_password(body.password.as_bytes(), &salt)
-        .map_err(|_| AppError::InternalServerError)
-        .map(|hash| hash.to_string())?;
+    data.password_policy
+        .check(&body.password, &[&body.username, &body.email])?;
+    let hashed_password = data.hashing.hash(&body.password)?;
 
-    let tx = data
+    let mut tx = data
         .db
         .begin()
         .await
         .map_err(|_| AppError::InternalServerError)?;
 
-    let user_id: Uuid = sqlx::query_scalar!(
-        "INSERT INTO users (username, email, password) VALUES ($1, $2, $3) RETURNING id",
-        body.username.to_string(),
-        body.email.to_string().to_ascii_lowercase(),
-        hashed_password
+    create_user_with_profile(
+        &mut tx,
+        &body.username,
+        &email,
+        Some(&hashed_password),
     )
-    .fetch_one(&data.db)
-    .await
-    .map_err(|_| AppError::InternalServerError)?;
-
-    sqlx::query!("INSERT INTO profiles (user_id, profile_image, bio) VALUES ($1, $2, $3) RETURNING id, user_id,profile_image,bio,created_at,updated_at",
-        user_id,
-        "default.jpg".to_string(),
-        "".to_string(),
-    )
-    .fetch_one(&data.db)
-    .await.map_err(|_| { AppError::InternalServerError})?;
+    .await?;
 
     tx.commit()
         .await
@@ -150,3 +151,247 @@ pub async fn status_handler(
     })));
     Ok(Json(response))
 }
+
+/// Accounts without a password set their first one by having logged in
+/// recently instead of confirming the current password.
+pub async fn change_password_handler(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppJson(body): AppJson<ChangePasswordSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    body.validate()?;
+    confirm_identity(
+        &session,
+        &data,
+        &user,
+        &body.current_password,
+        "current_password",
+    )
+    .await?;
+    data.password_policy
+        .check(&body.new_password, &[&user.username, &user.email])?;
+
+    let hashed_password = data.hashing.hash(&body.new_password)?;
+    sqlx::query!(
+        "UPDATE users SET password = $1, updated_at = NOW() WHERE id = $2",
+        hashed_password,
+        user.id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response: JsendResponse = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+pub async fn forgot_password_handler(
+    State(data): State<Arc<AppState>>,
+    AppJson(body): AppJson<ForgotPasswordSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    body.validate()?;
+    let mailer = data.mailer.as_ref().ok_or_else(|| {
+        AppError::JsendFail(json!({"email" : "password reset is not configured"}))
+    })?;
+    let email = body.email.to_ascii_lowercase();
+    let user_id = sqlx::query_scalar!("SELECT id FROM users WHERE email = $1", email)
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    // the response is the same whether or not the email exists
+    if let Some(user_id) = user_id {
+        let token = random_token();
+        sqlx::query!(
+            "INSERT INTO password_resets (user_id, token_hash, expires_at) VALUES ($1, $2, NOW() + INTERVAL '1 hour')",
+            user_id,
+            hash_token(&token)
+        )
+        .execute(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+        let body = format!(
+            "Use this token to reset your password, it expires in one hour:\n\n{}\n\nIf you did not ask for a password reset you can ignore this email.",
+            token
+        );
+        if let Err(err) = mailer.send(&email, "Reset your password", body).await {
+            tracing::warn!("password reset email for user {} not sent: {}", user_id, err);
+        }
+    }
+
+    let response: JsendResponse = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+pub async fn reset_password_handler(
+    State(data): State<Arc<AppState>>,
+    AppJson(body): AppJson<ResetPasswordSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    body.validate()?;
+    let user = sqlx::query_as!(
+        UserModel,
+        "SELECT users.* FROM password_resets
+        JOIN users ON users.id = password_resets.user_id
+        WHERE password_resets.token_hash = $1
+            AND password_resets.used_at IS NULL
+            AND password_resets.expires_at > NOW()",
+        hash_token(&body.token)
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or_else(|| AppError::JsendFail(json!({"token" : "reset token is invalid or expired"})))?;
+
+    data.password_policy
+        .check(&body.new_password, &[&user.username, &user.email])?;
+    let hashed_password = data.hashing.hash(&body.new_password)?;
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    // using one token burns every outstanding token for the account, a token
+    // raced by another reset finds nothing left to burn
+    let burned = sqlx::query!(
+        "UPDATE password_resets SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        user.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .rows_affected();
+    if burned == 0 {
+        return Err(AppError::JsendFail(
+            json!({"token" : "reset token is invalid or expired"}),
+        ));
+    }
+
+    sqlx::query!(
+        "UPDATE users SET password = $1, updated_at = NOW() WHERE id = $2",
+        hashed_password,
+        user.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let response: JsendResponse = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+/// Inserts the user row and its default profile, used by every signup path.
+pub async fn create_user_with_profile(
+    tx: &mut Transaction<'_, Postgres>,
+    username: &str,
+    email: &str,
+    hashed_password: Option<&str>,
+) -> Result<Uuid, AppError> {
+    let user_id: Uuid = sqlx::query_scalar!(
+        "INSERT INTO users (username, email, password) VALUES ($1, $2, $3) RETURNING id",
+        username,
+        email,
+        hashed_password
+    )
+    .fetch_one(&mut **tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    sqlx::query!(
+        "INSERT INTO profiles (user_id, profile_image, bio) VALUES ($1, $2, $3)",
+        user_id,
+        "default.jpg".to_string(),
+        "".to_string(),
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    Ok(user_id)
+}
+
+/// Marks the session as logged in, shared by every login method. Logging in
+/// also cancels a pending account deletion.
+pub async fn start_session(
+    session: &Session,
+    data: &AppState,
+    user_id: Option<Uuid>,
+) -> Result<(), AppError> {
+    let account = sqlx::query!(
+        "SELECT suspended_at IS NOT NULL AS \"suspended!\", session_epoch FROM users WHERE id = $1",
+        user_id
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    if account.suspended {
+        return Err(AppError::JsendFail(
+            json!({"account" : "account is suspended"}),
+        ));
+    }
+
+    sqlx::query!("DELETE FROM account_deletions WHERE user_id = $1", user_id)
+        .execute(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    session
+        .insert("user_id", user_id)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    session
+        .insert(SESSION_EPOCH, account.session_epoch)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    session
+        .insert(AUTHENTICATED_AT, Utc::now().timestamp())
+        .await
+        .map_err(|_| AppError::InternalServerError)
+}
+
+/// Session key holding when the user last proved who they are.
+const AUTHENTICATED_AT: &str = "authenticated_at";
+
+/// How long a login counts as confirmation for accounts without a password.
+const RECENT_LOGIN_SECONDS: i64 = 10 * 60;
+
+/// Confirms a sensitive action with the account password. Accounts without a
+/// password confirm it by having logged in within the last few minutes.
+pub async fn confirm_identity(
+    session: &Session,
+    data: &AppState,
+    user: &UserModel,
+    password: &str,
+    field: &str,
+) -> Result<(), AppError> {
+    match &user.password {
+        Some(password_hash) => {
+            if !data.hashing.verify(password, password_hash) {
+                return Err(AppError::JsendFail(
+                    json!({ field : "password is incorrect" }),
+                ));
+            }
+        }
+        None => {
+            let authenticated_at = session
+                .get::<i64>(AUTHENTICATED_AT)
+                .await
+                .map_err(|_| AppError::InternalServerError)?;
+            let recent = authenticated_at
+                .is_some_and(|at| Utc::now().timestamp() - at <= RECENT_LOGIN_SECONDS);
+            if !recent {
+                return Err(AppError::JsendFail(
+                    json!({ field : "log in again to confirm this change" }),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,238 @@
+use crate::{
+    handlers::user_handlers::find_user_id,
+    model::{ProfileResponse, UserModel},
+    realtime::RealtimeEvent,
+    response::{AppError, AppPath, AppQuery, JsendResponse},
+    schema::PaginationSchema,
+    AppState,
+};
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Whether either user has blocked the other. Anonymous viewers are never blocked.
+pub async fn is_blocked(
+    data: &AppState,
+    viewer: Option<Uuid>,
+    other: Uuid,
+) -> Result<bool, AppError> {
+    let Some(viewer) = viewer else {
+        return Ok(false);
+    };
+    sqlx::query_scalar!(
+        "SELECT EXISTS (
+            SELECT 1 FROM blocks
+            WHERE (blocker_id = $1 AND blocked_id = $2) OR (blocker_id = $2 AND blocked_id = $1)
+        )",
+        viewer,
+        other
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)
+    .map(|blocked| blocked.unwrap_or(false))
+}
+
+/// Whether the viewer has muted the other user.
+pub async fn is_muted(data: &AppState, viewer: Uuid, other: Uuid) -> Result<bool, AppError> {
+    sqlx::query_scalar!(
+        "SELECT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $1 AND muted_id = $2)",
+        viewer,
+        other
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)
+    .map(|muted| muted.unwrap_or(false))
+}
+
+pub async fn block_user(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(username): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let blocked_id = find_user_id(&data, &username).await?;
+    if user.id == Some(blocked_id) {
+        return Err(AppError::JsendFail(
+            json!({"block" : "you cannot block yourself"}),
+        ));
+    }
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    sqlx::query!(
+        "INSERT INTO blocks (blocker_id, blocked_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        user.id,
+        blocked_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    // blocking ends the follow relationship in both directions
+    sqlx::query!(
+        "DELETE FROM follows
+        WHERE (follower_id = $1 AND followee_id = $2) OR (follower_id = $2 AND followee_id = $1)",
+        user.id,
+        blocked_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    sqlx::query!(
+        "DELETE FROM follow_requests
+        WHERE (requester_id = $1 AND target_id = $2) OR (requester_id = $2 AND target_id = $1)",
+        user.id,
+        blocked_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    // live updates on each other's posts stop as well
+    if let Some(user_id) = user.id {
+        data.realtime
+            .publish(RealtimeEvent::VisibilityChanged {
+                user_ids: vec![user_id, blocked_id],
+            })
+            .await;
+    }
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+pub async fn unblock_user(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(username): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let blocked_id = find_user_id(&data, &username).await?;
+    sqlx::query!(
+        "DELETE FROM blocks WHERE blocker_id = $1 AND blocked_id = $2",
+        user.id,
+        blocked_id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+pub async fn mute_user(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(username): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let muted_id = find_user_id(&data, &username).await?;
+    if user.id == Some(muted_id) {
+        return Err(AppError::JsendFail(
+            json!({"mute" : "you cannot mute yourself"}),
+        ));
+    }
+
+    sqlx::query!(
+        "INSERT INTO mutes (muter_id, muted_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        user.id,
+        muted_id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+pub async fn unmute_user(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(username): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let muted_id = find_user_id(&data, &username).await?;
+    sqlx::query!(
+        "DELETE FROM mutes WHERE muter_id = $1 AND muted_id = $2",
+        user.id,
+        muted_id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+pub async fn get_blocked_users(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let users = sqlx::query_as!(
+        ProfileResponse,
+        "SELECT profiles.id AS profile_id, profiles.profile_image, users.username, profiles.display_name, profiles.bio, profiles.website, profiles.location, profiles.pronouns
+        FROM blocks
+        JOIN users ON users.id = blocks.blocked_id
+        JOIN profiles ON profiles.user_id = users.id
+        WHERE blocks.blocker_id = $1
+        ORDER BY blocks.created_at DESC
+        LIMIT $2 OFFSET $3",
+        user.id,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "users" : users,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
+
+pub async fn get_muted_users(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let users = sqlx::query_as!(
+        ProfileResponse,
+        "SELECT profiles.id AS profile_id, profiles.profile_image, users.username, profiles.display_name, profiles.bio, profiles.website, profiles.location, profiles.pronouns
+        FROM mutes
+        JOIN users ON users.id = mutes.muted_id
+        JOIN profiles ON profiles.user_id = users.id
+        WHERE mutes.muter_id = $1
+        ORDER BY mutes.created_at DESC
+        LIMIT $2 OFFSET $3",
+        user.id,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "users" : users,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
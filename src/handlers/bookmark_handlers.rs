@@ -0,0 +1,102 @@
+use crate::{
+    handlers::{block_handlers::is_blocked, follow_handlers::can_view_posts},
+    model::{PostResponse, UserModel},
+    response::{AppError, AppPath, AppQuery, JsendResponse},
+    schema::PaginationSchema,
+    AppState,
+};
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+use serde_json::json;
+use sqlx::types::Json as SqlJson;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+pub async fn bookmark_post(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(post_id): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let post_id = Uuid::parse_str(&post_id)
+        .map_err(|_| AppError::JsendFail(json!({"post_id" : "not a valid UUID"})))?;
+
+    let author_id = sqlx::query_scalar!(
+        "SELECT user_id FROM posts WHERE id = $1 AND status = 'published'",
+        post_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or(AppError::JsendFail(json!({"post" : "post doesnt exist"})))?;
+    if is_blocked(&data, user.id, author_id).await?
+        || !can_view_posts(&data, user.id, author_id).await?
+    {
+        return Err(AppError::JsendFail(json!({"post" : "post doesnt exist"})));
+    }
+
+    sqlx::query!(
+        "INSERT INTO bookmarks (user_id, post_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        user.id,
+        post_id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+pub async fn unbookmark_post(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(post_id): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let post_id = Uuid::parse_str(&post_id)
+        .map_err(|_| AppError::JsendFail(json!({"post_id" : "not a valid UUID"})))?;
+
+    sqlx::query!(
+        "DELETE FROM bookmarks WHERE user_id = $1 AND post_id = $2",
+        user.id,
+        post_id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+/// Most recently saved first. Bookmarks stay saved but are hidden while the
+/// author is blocked, muted or has made their account private.
+pub async fn get_bookmarks(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let posts: Vec<SqlJson<PostResponse>> = sqlx::query_scalar!(
+        "SELECT post_response(posts.id, $1) AS \"post!: SqlJson<PostResponse>\"
+        FROM bookmarks
+        JOIN posts ON posts.id = bookmarks.post_id
+        WHERE bookmarks.user_id = $1
+            AND can_view_posts_of(posts.user_id, $1)
+            AND NOT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $1 AND muted_id = posts.user_id)
+        ORDER BY bookmarks.created_at DESC
+        LIMIT $2 OFFSET $3",
+        user.id,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "posts" : posts,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
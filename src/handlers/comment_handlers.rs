@@ -1,32 +1,61 @@
 use crate::{
-    model::{CommentResponse,UserModel},
-    response::{AppError, AppJson, AppPath, JsendResponse},
-    schema::CommentSchema, 
+    handlers::{
+        block_handlers::{is_blocked, is_muted},
+        follow_handlers::can_view_posts,
+        user_handlers::find_user_id,
+    },
+    mentions::save_mentions,
+    notifications::{notify, NotificationKind},
+    model::{CommentResponse, ContentHtml, UserModel},
+    realtime::{CommentAction, RealtimeEvent},
+    response::{AppError, AppJson, AppPath, AppQuery, JsendResponse},
+    schema::{CommentSchema, PaginationSchema},
+    session_auth::{current_user_id, session_ended, session_user},
     AppState,
 };
 use axum::{
     extract::State,
-    response::IntoResponse,
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
     Extension, Json,
 };
+use futures_util::stream::{self, Stream};
 use serde_json::json;
+use sqlx::{types::Json as SqlJson, Postgres, Transaction};
 use validator::Validate;
-use std::sync::Arc;
+use std::{convert::Infallible, sync::Arc};
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tower_sessions::Session;
 use uuid::Uuid;
 
 pub async fn get_comments_handler(
+    session: Session,
     AppPath(postid): AppPath<String>,
     State(data): State<Arc<AppState>>,
 ) -> Result<impl IntoResponse, AppError> {
     let postid = Uuid::parse_str(&postid).map_err(|_| AppError::JsendFail(json!({"post_id" : "not a valid UUID"})))?;
+    let viewer = current_user_id(&session, &data).await?;
+    let author_id = post_author(&data, postid).await?;
+    if is_blocked(&data, viewer, author_id).await? || !can_view_posts(&data, viewer, author_id).await? {
+        return Err(AppError::JsendFail(json!({"post" : "post doesnt exist"})));
+    }
     let comments = sqlx::query_as!(
         CommentResponse,
         "SELECT
             comments.id,
             users.username,
+            profiles.display_name,
+            profiles.pronouns,
+            profiles.bio,
+            profiles.website,
+            profiles.location,
             comments.user_id,
             comments.post_id,
             comments.content,
+            content_with_mentions(comments.content, comments.post_id, comments.id) AS \"content_html!: SqlJson<ContentHtml>\",
             comments.created_at,
             comments.updated_at,
             profiles.profile_image
@@ -34,8 +63,15 @@ pub async fn get_comments_handler(
         JOIN users ON comments.user_id = users.id
         JOIN profiles ON comments.user_id = profiles.user_id
         WHERE comments.post_id = $1
+            AND NOT EXISTS (
+                SELECT 1 FROM blocks
+                WHERE (blocker_id = $2 AND blocked_id = comments.user_id)
+                    OR (blocker_id = comments.user_id AND blocked_id = $2)
+            )
+            AND NOT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $2 AND muted_id = comments.user_id)
         ORDER BY comments.created_at DESC",
-        postid
+        postid,
+        viewer
     )
     .fetch_all(&data.db)
     .await
@@ -46,6 +82,71 @@ pub async fn get_comments_handler(
     Ok(Json(response))
 }
 
+pub async fn get_user_comments_handler(
+    session: Session,
+    AppPath(username): AppPath<String>,
+    State(data): State<Arc<AppState>>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let viewer = current_user_id(&session, &data).await?;
+    let user_id = find_user_id(&data, &username).await?;
+    if is_blocked(&data, viewer, user_id).await? {
+        return Err(AppError::JsendFail(json!({"username" : "user does not exist"})));
+    }
+
+    // comments on posts by authors hidden from the viewer, or on posts of private
+    // accounts the viewer does not follow, are left out
+    let comments = sqlx::query_as!(
+        CommentResponse,
+        "SELECT
+            comments.id,
+            users.username,
+            profiles.display_name,
+            profiles.pronouns,
+            profiles.bio,
+            profiles.website,
+            profiles.location,
+            comments.user_id,
+            comments.post_id,
+            comments.content,
+            content_with_mentions(comments.content, comments.post_id, comments.id) AS \"content_html!: SqlJson<ContentHtml>\",
+            comments.created_at,
+            comments.updated_at,
+            profiles.profile_image
+        FROM comments
+        JOIN users ON comments.user_id = users.id
+        JOIN profiles ON comments.user_id = profiles.user_id
+        JOIN posts ON comments.post_id = posts.id
+        JOIN profiles AS authors ON posts.user_id = authors.user_id
+        WHERE comments.user_id = $1
+            AND NOT EXISTS (
+                SELECT 1 FROM blocks
+                WHERE (blocker_id = $2 AND blocked_id = posts.user_id)
+                    OR (blocker_id = posts.user_id AND blocked_id = $2)
+            )
+            AND (NOT authors.is_private
+                OR posts.user_id = $2
+                OR EXISTS (SELECT 1 FROM follows WHERE follower_id = $2 AND followee_id = posts.user_id))
+        ORDER BY comments.created_at DESC
+        LIMIT $3 OFFSET $4",
+        user_id,
+        viewer,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "comments" : comments,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
+
 pub async fn create_comment_handler(
     State(data): State<Arc<AppState>>,
     Extension(user): Extension<UserModel>,
@@ -54,15 +155,426 @@ pub async fn create_comment_handler(
 ) -> Result<impl IntoResponse, AppError> {
     comment.validate()?;
     let postid = Uuid::parse_str(&postid).map_err(|_| AppError::JsendFail(json!({"post_id" : "not a valid UUID"})))?;
-    sqlx::query!(
-        "INSERT INTO comments (content,user_id,post_id) VALUES ($1,$2,$3)",
+    let author_id = post_author(&data, postid).await?;
+    if is_blocked(&data, user.id, author_id).await? || !can_view_posts(&data, user.id, author_id).await? {
+        return Err(AppError::JsendFail(
+            json!({"authorization" : "you cannot comment on this post"}),
+        ));
+    }
+    let user_id = user.id.ok_or(AppError::InternalServerError)?;
+    let mut tx = data.db.begin().await.map_err(|_| AppError::InternalServerError)?;
+    lock_post(&mut tx, postid).await?;
+    let comment_id = sqlx::query_scalar!(
+        "INSERT INTO comments (content,user_id,post_id) VALUES ($1,$2,$3) RETURNING id",
         comment.content,
-        user.id,
+        user_id,
         postid
     )
-    .execute(&data.db)
+    .fetch_one(&mut *tx)
     .await
     .map_err(|_| AppError::InternalServerError)?;
+    let mut notified = Vec::new();
+    if notify(&mut *tx, author_id, user_id, NotificationKind::Comment, Some(postid), Some(comment_id)).await? {
+        notified.push((author_id, NotificationKind::Comment));
+    }
+    for mentioned in save_mentions(&mut tx, user_id, postid, Some(comment_id), &comment.content).await? {
+        // users who cannot see the post hear nothing about comments on it
+        if can_view_posts(&data, Some(mentioned), author_id).await?
+            && notify(&mut *tx, mentioned, user_id, NotificationKind::Mention, Some(postid), Some(comment_id)).await?
+        {
+            notified.push((mentioned, NotificationKind::Mention));
+        }
+    }
+    let event = record_comment_event(&mut tx, postid, comment_id, user_id, CommentAction::Created).await?;
+    tx.commit().await.map_err(|_| AppError::InternalServerError)?;
+
+    data.realtime.publish(event).await;
+    for (recipient, kind) in notified {
+        data.realtime.publish(RealtimeEvent::notification(recipient, kind)).await;
+    }
+    let response = JsendResponse::success(Some(json!({
+        "comment_id" : comment_id
+    })));
+    Ok(Json(response))
+}
+
+async fn post_author(data: &AppState, post_id: Uuid) -> Result<Uuid, AppError> {
+    sqlx::query_scalar!(
+        "SELECT user_id FROM posts WHERE id = $1 AND status = 'published'",
+        post_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or(AppError::JsendFail(json!({"post" : "post doesnt exist"})))
+}
+
+pub async fn update_comment_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath((postid, commentid)): AppPath<(String, String)>,
+    AppJson(comment): AppJson<CommentSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    comment.validate()?;
+    let (postid, commentid) = parse_comment_path(&postid, &commentid)?;
+    let user_id = user.id.ok_or(AppError::InternalServerError)?;
+    let author_id = post_author(&data, postid).await?;
+    let mut tx = data.db.begin().await.map_err(|_| AppError::InternalServerError)?;
+    lock_post(&mut tx, postid).await?;
+    let updated = sqlx::query!(
+        "UPDATE comments SET content = $1, updated_at = NOW() WHERE id = $2 AND post_id = $3 AND user_id = $4",
+        comment.content,
+        commentid,
+        postid,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    if updated.rows_affected() == 0 {
+        return Err(AppError::JsendFail(json!({"comment" : "comment doesnt exist"})));
+    }
+
+    // only people who were not mentioned before hear about the edit
+    let previously_mentioned = sqlx::query_scalar!(
+        "DELETE FROM mentions WHERE comment_id = $1 RETURNING user_id",
+        commentid
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    let mut notified = Vec::new();
+    for mentioned in save_mentions(&mut tx, user_id, postid, Some(commentid), &comment.content).await? {
+        if !previously_mentioned.contains(&mentioned)
+            && can_view_posts(&data, Some(mentioned), author_id).await?
+            && notify(&mut *tx, mentioned, user_id, NotificationKind::Mention, Some(postid), Some(commentid)).await?
+        {
+            notified.push(mentioned);
+        }
+    }
+    let event = record_comment_event(&mut tx, postid, commentid, user_id, CommentAction::Edited).await?;
+    tx.commit().await.map_err(|_| AppError::InternalServerError)?;
+
+    data.realtime.publish(event).await;
+    for recipient in notified {
+        data.realtime.publish(RealtimeEvent::notification(recipient, NotificationKind::Mention)).await;
+    }
     let response = JsendResponse::success(None);
     Ok(Json(response))
-}
\ No newline at end of file
+}
+
+pub async fn delete_comment_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath((postid, commentid)): AppPath<(String, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let (postid, commentid) = parse_comment_path(&postid, &commentid)?;
+    let user_id = user.id.ok_or(AppError::InternalServerError)?;
+    let mut tx = data.db.begin().await.map_err(|_| AppError::InternalServerError)?;
+    lock_post(&mut tx, postid).await?;
+    let deleted = sqlx::query!(
+        "DELETE FROM comments WHERE id = $1 AND post_id = $2 AND user_id = $3",
+        commentid,
+        postid,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    if deleted.rows_affected() == 0 {
+        return Err(AppError::JsendFail(json!({"comment" : "comment doesnt exist"})));
+    }
+    let event = record_comment_event(&mut tx, postid, commentid, user_id, CommentAction::Deleted).await?;
+    tx.commit().await.map_err(|_| AppError::InternalServerError)?;
+
+    data.realtime.publish(event).await;
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+/// Streams comment changes on a post as server-sent events. Every event carries
+/// its id, so a reconnecting client sending `Last-Event-ID` first gets the
+/// changes it missed. The stream ends once the viewer may no longer see the post.
+pub async fn stream_comments_handler(
+    session: Session,
+    headers: HeaderMap,
+    AppPath(postid): AppPath<String>,
+    State(data): State<Arc<AppState>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let postid = Uuid::parse_str(&postid).map_err(|_| AppError::JsendFail(json!({"post_id" : "not a valid UUID"})))?;
+    // a logged in viewer's session and its epoch, the stream ends with the session
+    let viewer_session = session_user(&session, &data)
+        .await?
+        .and_then(|user| user.id.map(|user_id| (user_id, user.session_epoch)));
+    let viewer = viewer_session.map(|(user_id, _)| user_id);
+    let author_id = post_author(&data, postid).await?;
+    if !can_see_post(&data, viewer, author_id).await? {
+        return Err(AppError::JsendFail(json!({"post" : "post doesnt exist"})));
+    }
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i64>().ok());
+
+    // subscribe before replaying so nothing published in between is lost
+    let mut live = data.realtime.subscribe();
+    let (sender, receiver) = mpsc::channel::<Event>(64);
+    tokio::spawn(async move {
+        if let Some((user_id, session_epoch)) = viewer_session {
+            if session_ended(&data, user_id, session_epoch).await.unwrap_or(true) {
+                return;
+            }
+        }
+        // id of the last event the client has, fresh streams start at the newest one
+        let mut last_sent = match last_event_id {
+            Some(last_event_id) => last_event_id,
+            None => match latest_comment_event(&data, postid).await {
+                Ok(latest) => latest,
+                Err(_) => return,
+            },
+        };
+        if last_event_id.is_some() && replay_comment_events(&data, &sender, viewer, postid, &mut last_sent).await.is_err() {
+            return;
+        }
+
+        loop {
+            // stops as soon as the client disconnects instead of on the next event
+            let event = tokio::select! {
+                _ = sender.closed() => return,
+                event = live.recv() => event,
+            };
+            match event.map(|envelope| envelope.event) {
+                Ok(RealtimeEvent::Comment { event_id, post_id, comment_id, user_id, action }) => {
+                    // replayed already
+                    if post_id != postid || event_id <= last_sent {
+                        continue;
+                    }
+                    last_sent = event_id;
+                    match comment_event(&data, viewer, event_id, comment_id, user_id, action).await {
+                        Ok(Some(event)) => {
+                            if sender.send(event).await.is_err() {
+                                return;
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(_) => return,
+                    }
+                }
+                // a block or privacy change may hide the post from the viewer
+                Ok(RealtimeEvent::VisibilityChanged { user_ids }) if user_ids.contains(&author_id) => {
+                    match can_see_post(&data, viewer, author_id).await {
+                        Ok(true) => {}
+                        _ => return,
+                    }
+                }
+                Ok(event) if viewer_session.is_some_and(|(user_id, session_epoch)| event.ends_session(user_id, session_epoch)) => return,
+                Ok(_) => {}
+                // the skipped events are still in comment_events
+                Err(RecvError::Lagged(_)) => {
+                    if replay_comment_events(&data, &sender, viewer, postid, &mut last_sent).await.is_err() {
+                        return;
+                    }
+                }
+                Err(RecvError::Closed) => return,
+            }
+        }
+    });
+
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|event| (Ok(event), receiver))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+async fn can_see_post(data: &AppState, viewer: Option<Uuid>, author_id: Uuid) -> Result<bool, AppError> {
+    Ok(!is_blocked(data, viewer, author_id).await? && can_view_posts(data, viewer, author_id).await?)
+}
+
+async fn latest_comment_event(data: &AppState, post_id: Uuid) -> Result<i64, AppError> {
+    sqlx::query_scalar!("SELECT COALESCE(MAX(id), 0) AS \"id!\" FROM comment_events WHERE post_id = $1", post_id)
+        .fetch_one(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)
+}
+
+/// Sends the stored comment events after `last_sent` and advances it. Fails
+/// when the client is gone or the events cannot be loaded, ending the stream.
+async fn replay_comment_events(
+    data: &AppState,
+    sender: &mpsc::Sender<Event>,
+    viewer: Option<Uuid>,
+    post_id: Uuid,
+    last_sent: &mut i64,
+) -> Result<(), AppError> {
+    let missed = sqlx::query!(
+        "SELECT id, comment_id, user_id, action FROM comment_events WHERE post_id = $1 AND id > $2 ORDER BY id",
+        post_id,
+        *last_sent
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    for missed in missed {
+        *last_sent = missed.id;
+        let Some(action) = CommentAction::parse(&missed.action) else {
+            continue;
+        };
+        if let Some(event) = comment_event(data, viewer, missed.id, missed.comment_id, missed.user_id, action).await? {
+            sender.send(event).await.map_err(|_| AppError::InternalServerError)?;
+        }
+    }
+    Ok(())
+}
+
+/// Builds the event for a comment change. Created and edited events carry the
+/// current comment, changes by users hidden from the viewer are skipped.
+async fn comment_event(
+    data: &AppState,
+    viewer: Option<Uuid>,
+    event_id: i64,
+    comment_id: Uuid,
+    user_id: Uuid,
+    action: CommentAction,
+) -> Result<Option<Event>, AppError> {
+    if is_blocked(data, viewer, user_id).await? {
+        return Ok(None);
+    }
+    if let Some(viewer) = viewer {
+        if is_muted(data, viewer, user_id).await? {
+            return Ok(None);
+        }
+    }
+    let comment = match action {
+        CommentAction::Deleted => None,
+        CommentAction::Created | CommentAction::Edited => sqlx::query_as!(
+            CommentResponse,
+            "SELECT
+                comments.id,
+                users.username,
+                profiles.display_name,
+                profiles.pronouns,
+                profiles.bio,
+                profiles.website,
+                profiles.location,
+                comments.user_id,
+                comments.post_id,
+                comments.content,
+                content_with_mentions(comments.content, comments.post_id, comments.id) AS \"content_html!: SqlJson<ContentHtml>\",
+                comments.created_at,
+                comments.updated_at,
+                profiles.profile_image
+            FROM comments
+            JOIN users ON comments.user_id = users.id
+            JOIN profiles ON comments.user_id = profiles.user_id
+            WHERE comments.id = $1",
+            comment_id
+        )
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?,
+    };
+
+    Event::default()
+        .id(event_id.to_string())
+        .event(action.as_str())
+        .json_data(json!({
+            "comment_id" : comment_id,
+            "comment" : comment,
+        }))
+        .map(Some)
+        .map_err(|_| AppError::InternalServerError)
+}
+
+/// Serializes the comment changes of a post until the transaction ends. Event
+/// ids come from a sequence, so without the lock a change could commit after
+/// one with a higher id and be skipped by streams that already moved past it.
+/// Taken before anything else in the transaction.
+async fn lock_post(tx: &mut Transaction<'_, Postgres>, post_id: Uuid) -> Result<(), AppError> {
+    sqlx::query_scalar!("SELECT id FROM posts WHERE id = $1 FOR UPDATE", post_id)
+        .fetch_optional(&mut **tx)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .ok_or(AppError::JsendFail(json!({"post" : "post doesnt exist"})))?;
+    Ok(())
+}
+
+/// The transaction has to hold the post lock from `lock_post`.
+async fn record_comment_event(
+    tx: &mut Transaction<'_, Postgres>,
+    post_id: Uuid,
+    comment_id: Uuid,
+    user_id: Uuid,
+    action: CommentAction,
+) -> Result<RealtimeEvent, AppError> {
+    let event_id = sqlx::query_scalar!(
+        "INSERT INTO comment_events (post_id, comment_id, user_id, action) VALUES ($1,$2,$3,$4) RETURNING id",
+        post_id,
+        comment_id,
+        user_id,
+        action.as_str()
+    )
+    .fetch_one(&mut **tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    Ok(RealtimeEvent::Comment { event_id, post_id, comment_id, user_id, action })
+}
+
+fn parse_comment_path(postid: &str, commentid: &str) -> Result<(Uuid, Uuid), AppError> {
+    let postid = Uuid::parse_str(postid).map_err(|_| AppError::JsendFail(json!({"post_id" : "not a valid UUID"})))?;
+    let commentid = Uuid::parse_str(commentid).map_err(|_| AppError::JsendFail(json!({"comment_id" : "not a valid UUID"})))?;
+    Ok((postid, commentid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::auth_handlers::create_user_with_profile;
+    use sqlx::PgPool;
+    use std::time::Duration;
+
+    fn event_id(event: &RealtimeEvent) -> i64 {
+        match event {
+            RealtimeEvent::Comment { event_id, .. } => *event_id,
+            _ => unreachable!(),
+        }
+    }
+
+    #[sqlx::test]
+    async fn comment_events_of_a_post_commit_in_id_order(db: PgPool) {
+        let mut tx = db.begin().await.unwrap();
+        let user_id = create_user_with_profile(&mut tx, "author", "author@example.com", None).await.unwrap();
+        tx.commit().await.unwrap();
+        let post_id = sqlx::query_scalar!("INSERT INTO posts (user_id, title, content) VALUES ($1, 'title', 'content') RETURNING id", user_id)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        let comment_id = sqlx::query_scalar!("INSERT INTO comments (content, user_id, post_id) VALUES ('comment', $1, $2) RETURNING id", user_id, post_id)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+
+        let mut first = db.begin().await.unwrap();
+        lock_post(&mut first, post_id).await.unwrap();
+        let first_event = record_comment_event(&mut first, post_id, comment_id, user_id, CommentAction::Edited).await.unwrap();
+
+        let second = std::thread::spawn({
+            let db = db.clone();
+            move || {
+                tokio::runtime::Runtime::new().unwrap().block_on(async move {
+                    let mut tx = db.begin().await.unwrap();
+                    lock_post(&mut tx, post_id).await.unwrap();
+                    let event = record_comment_event(&mut tx, post_id, comment_id, user_id, CommentAction::Deleted).await.unwrap();
+                    tx.commit().await.unwrap();
+                    event
+                })
+            }
+        });
+
+        // the second change waits for the first instead of committing a higher id ahead of it
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(!second.is_finished());
+        first.commit().await.unwrap();
+        let second_event = second.join().unwrap();
+        assert!(event_id(&second_event) > event_id(&first_event));
+    }
+}
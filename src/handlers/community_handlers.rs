@@ -0,0 +1,496 @@
+use crate::{
+    handlers::user_handlers::find_user_id,
+    model::{CommunityMemberResponse, CommunityResponse, PostResponse, UserModel},
+    response::{AppError, AppJson, AppPath, AppQuery, JsendResponse},
+    schema::{CommunitySchema, PaginationSchema, UpdateCommunitySchema},
+    session_auth::current_user_id,
+    AppState,
+};
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+use serde_json::json;
+use sqlx::types::Json as SqlJson;
+use std::sync::Arc;
+use tower_sessions::Session;
+use uuid::Uuid;
+use validator::Validate;
+
+/// A user's standing in a community. Banned users keep their membership row so
+/// that leaving and rejoining does not lift the ban.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommunityRole {
+    Owner,
+    Moderator,
+    Member,
+    Banned,
+}
+
+impl CommunityRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CommunityRole::Owner => "owner",
+            CommunityRole::Moderator => "moderator",
+            CommunityRole::Member => "member",
+            CommunityRole::Banned => "banned",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<CommunityRole> {
+        match role {
+            "owner" => Some(CommunityRole::Owner),
+            "moderator" => Some(CommunityRole::Moderator),
+            "member" => Some(CommunityRole::Member),
+            "banned" => Some(CommunityRole::Banned),
+            _ => None,
+        }
+    }
+
+    pub fn is_member(self) -> bool {
+        self != CommunityRole::Banned
+    }
+
+    pub fn can_moderate(self) -> bool {
+        matches!(self, CommunityRole::Owner | CommunityRole::Moderator)
+    }
+}
+
+/// The user's role in the community, None for anonymous viewers and users who
+/// never joined.
+pub async fn community_role(
+    data: &AppState,
+    community_id: Uuid,
+    user_id: Option<Uuid>,
+) -> Result<Option<CommunityRole>, AppError> {
+    let Some(user_id) = user_id else {
+        return Ok(None);
+    };
+    let role = sqlx::query_scalar!(
+        "SELECT role FROM community_members WHERE community_id = $1 AND user_id = $2",
+        community_id,
+        user_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    Ok(role.as_deref().and_then(CommunityRole::parse))
+}
+
+async fn find_community_id(data: &AppState, name: &str) -> Result<Uuid, AppError> {
+    sqlx::query_scalar!(
+        "SELECT id FROM communities WHERE name = $1",
+        name.to_lowercase()
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or_else(|| AppError::JsendFail(json!({"community" : "community does not exist"})))
+}
+
+async fn require_moderator(
+    data: &AppState,
+    community_id: Uuid,
+    user_id: Option<Uuid>,
+) -> Result<CommunityRole, AppError> {
+    match community_role(data, community_id, user_id).await? {
+        Some(role) if role.can_moderate() => Ok(role),
+        _ => Err(AppError::JsendFail(
+            json!({"authorization" : "only moderators can do this"}),
+        )),
+    }
+}
+
+/// Community names are case insensitive and stored lowercased.
+pub async fn create_community(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppJson(community): AppJson<CommunitySchema>,
+) -> Result<impl IntoResponse, AppError> {
+    community.validate()?;
+    let name = community.name.to_lowercase();
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let community_id = sqlx::query_scalar!(
+        "INSERT INTO communities (name, description, created_by) VALUES ($1, $2, $3)
+        ON CONFLICT (name) DO NOTHING
+        RETURNING id",
+        name,
+        community.description,
+        user.id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or_else(|| AppError::JsendFail(json!({"name" : "name already taken"})))?;
+
+    sqlx::query!(
+        "INSERT INTO community_members (community_id, user_id, role) VALUES ($1, $2, $3)",
+        community_id,
+        user.id,
+        CommunityRole::Owner.as_str()
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "community_id" : community_id,
+        "name" : name,
+    })));
+    Ok(Json(response))
+}
+
+/// Largest communities first.
+pub async fn get_communities(
+    State(data): State<Arc<AppState>>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let communities = sqlx::query_as!(
+        CommunityResponse,
+        r#"SELECT
+            communities.id,
+            communities.name,
+            communities.description,
+            COUNT(community_members.user_id) AS "member_count!",
+            communities.created_at
+        FROM communities
+        LEFT JOIN community_members ON community_members.community_id = communities.id
+            AND community_members.role <> 'banned'
+        GROUP BY communities.id
+        ORDER BY COUNT(community_members.user_id) DESC, communities.created_at
+        LIMIT $1 OFFSET $2"#,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "communities" : communities,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
+
+pub async fn get_community(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    AppPath(name): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let viewer = current_user_id(&session, &data).await?;
+    let community = sqlx::query_as!(
+        CommunityResponse,
+        r#"SELECT
+            communities.id,
+            communities.name,
+            communities.description,
+            COUNT(community_members.user_id) AS "member_count!",
+            communities.created_at
+        FROM communities
+        LEFT JOIN community_members ON community_members.community_id = communities.id
+            AND community_members.role <> 'banned'
+        WHERE communities.name = $1
+        GROUP BY communities.id"#,
+        name.to_lowercase()
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or_else(|| AppError::JsendFail(json!({"community" : "community does not exist"})))?;
+
+    let role = community_role(&data, community.id, viewer).await?;
+
+    let response = JsendResponse::success(Some(json!({
+        "community" : community,
+        "role" : role.map(CommunityRole::as_str),
+    })));
+    Ok(Json(response))
+}
+
+pub async fn update_community(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(name): AppPath<String>,
+    AppJson(community): AppJson<UpdateCommunitySchema>,
+) -> Result<impl IntoResponse, AppError> {
+    community.validate()?;
+    let community_id = find_community_id(&data, &name).await?;
+    require_moderator(&data, community_id, user.id).await?;
+
+    sqlx::query!(
+        "UPDATE communities SET description = COALESCE($1, description), updated_at = NOW()
+        WHERE id = $2",
+        community.description,
+        community_id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+pub async fn join_community(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(name): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let community_id = find_community_id(&data, &name).await?;
+    if community_role(&data, community_id, user.id).await? == Some(CommunityRole::Banned) {
+        return Err(AppError::JsendFail(
+            json!({"community" : "you are banned from this community"}),
+        ));
+    }
+
+    sqlx::query!(
+        "INSERT INTO community_members (community_id, user_id) VALUES ($1, $2)
+        ON CONFLICT DO NOTHING",
+        community_id,
+        user.id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+/// The owner cannot leave, and leaving does not lift a ban.
+pub async fn leave_community(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(name): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let community_id = find_community_id(&data, &name).await?;
+    if community_role(&data, community_id, user.id).await? == Some(CommunityRole::Owner) {
+        return Err(AppError::JsendFail(
+            json!({"community" : "the owner cannot leave the community"}),
+        ));
+    }
+
+    sqlx::query!(
+        "DELETE FROM community_members
+        WHERE community_id = $1 AND user_id = $2 AND role IN ('member', 'moderator')",
+        community_id,
+        user.id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+/// Same visibility rules as the other post listings.
+pub async fn get_community_posts(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    AppPath(name): AppPath<String>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let viewer = current_user_id(&session, &data).await?;
+    let community_id = find_community_id(&data, &name).await?;
+
+    let posts: Vec<SqlJson<PostResponse>> = sqlx::query_scalar!(
+        "SELECT post_response(posts.id, $2) AS \"post!: SqlJson<PostResponse>\"
+        FROM posts
+        WHERE posts.community_id = $1
+            AND posts.status = 'published'
+            AND can_view_posts_of(posts.user_id, $2)
+            AND NOT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $2 AND muted_id = posts.user_id)
+        ORDER BY posts.created_at DESC
+        LIMIT $3 OFFSET $4",
+        community_id,
+        viewer,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "community" : name.to_lowercase(),
+        "posts" : posts,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
+
+/// Owner first, then moderators, then members in the order they joined.
+pub async fn get_community_members(
+    State(data): State<Arc<AppState>>,
+    AppPath(name): AppPath<String>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let community_id = find_community_id(&data, &name).await?;
+
+    let members = sqlx::query_as!(
+        CommunityMemberResponse,
+        "SELECT
+            users.username,
+            profiles.display_name,
+            profiles.profile_image,
+            community_members.role,
+            community_members.joined_at
+        FROM community_members
+        JOIN users ON users.id = community_members.user_id
+        JOIN profiles ON profiles.user_id = users.id
+        WHERE community_members.community_id = $1
+            AND community_members.role <> 'banned'
+            AND users.suspended_at IS NULL
+        ORDER BY
+            CASE community_members.role WHEN 'owner' THEN 0 WHEN 'moderator' THEN 1 ELSE 2 END,
+            community_members.joined_at
+        LIMIT $2 OFFSET $3",
+        community_id,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "members" : members,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
+
+/// Changes a member's role between member and moderator. Only the owner
+/// appoints and removes moderators.
+async fn set_moderator(
+    data: &AppState,
+    user: &UserModel,
+    name: &str,
+    username: &str,
+    role: CommunityRole,
+) -> Result<(), AppError> {
+    let community_id = find_community_id(data, name).await?;
+    if community_role(data, community_id, user.id).await? != Some(CommunityRole::Owner) {
+        return Err(AppError::JsendFail(
+            json!({"authorization" : "only the owner can manage moderators"}),
+        ));
+    }
+    let member_id = find_user_id(data, username).await?;
+
+    let updated = sqlx::query!(
+        "UPDATE community_members SET role = $1
+        WHERE community_id = $2 AND user_id = $3 AND role IN ('member', 'moderator')",
+        role.as_str(),
+        community_id,
+        member_id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    if updated.rows_affected() == 0 {
+        return Err(AppError::JsendFail(
+            json!({"username" : "user is not a member of this community"}),
+        ));
+    }
+    Ok(())
+}
+
+pub async fn add_moderator(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath((name, username)): AppPath<(String, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    set_moderator(&data, &user, &name, &username, CommunityRole::Moderator).await?;
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+pub async fn remove_moderator(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath((name, username)): AppPath<(String, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    set_moderator(&data, &user, &name, &username, CommunityRole::Member).await?;
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+/// Moderators can ban members, only the owner can ban moderators. Banned users
+/// can no longer post in the community; their existing posts are removed
+/// separately.
+pub async fn ban_member(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath((name, username)): AppPath<(String, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let community_id = find_community_id(&data, &name).await?;
+    let role = require_moderator(&data, community_id, user.id).await?;
+    let member_id = find_user_id(&data, &username).await?;
+    if user.id == Some(member_id) {
+        return Err(AppError::JsendFail(
+            json!({"ban" : "you cannot ban yourself"}),
+        ));
+    }
+
+    let allowed = match community_role(&data, community_id, Some(member_id)).await? {
+        Some(CommunityRole::Owner) => false,
+        Some(CommunityRole::Moderator) => role == CommunityRole::Owner,
+        _ => true,
+    };
+    if !allowed {
+        return Err(AppError::JsendFail(
+            json!({"authorization" : "user not authorized to ban this member"}),
+        ));
+    }
+
+    sqlx::query!(
+        "INSERT INTO community_members (community_id, user_id, role) VALUES ($1, $2, $3)
+        ON CONFLICT (community_id, user_id) DO UPDATE SET role = EXCLUDED.role",
+        community_id,
+        member_id,
+        CommunityRole::Banned.as_str()
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+pub async fn unban_member(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath((name, username)): AppPath<(String, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let community_id = find_community_id(&data, &name).await?;
+    require_moderator(&data, community_id, user.id).await?;
+    let member_id = find_user_id(&data, &username).await?;
+
+    sqlx::query!(
+        "DELETE FROM community_members WHERE community_id = $1 AND user_id = $2 AND role = 'banned'",
+        community_id,
+        member_id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
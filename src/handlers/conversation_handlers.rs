@@ -0,0 +1,703 @@
+use crate::{
+    handlers::{block_handlers::is_blocked, user_handlers::find_user_id},
+    model::{ConversationResponse, MessageResponse, UserModel},
+    realtime::{Audience, RealtimeEvent},
+    response::{AppError, AppJson, AppPath, AppQuery, JsendResponse},
+    schema::{CreateConversationSchema, MessageSchema, PaginationSchema},
+    session_auth::session_ended,
+    AppState,
+};
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Extension, Json,
+};
+use futures_util::stream::{self, Stream};
+use serde_json::json;
+use sqlx::{PgExecutor, Postgres, Transaction};
+use std::{convert::Infallible, sync::Arc};
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use uuid::Uuid;
+use validator::Validate;
+
+/// Fails unless the user is a member, so conversations of others look like
+/// they do not exist.
+async fn ensure_member(
+    data: &AppState,
+    conversation_id: Uuid,
+    user_id: Option<Uuid>,
+) -> Result<(), AppError> {
+    let is_member: bool = sqlx::query_scalar!(
+        "SELECT EXISTS (SELECT 1 FROM conversation_members WHERE conversation_id = $1 AND user_id = $2)",
+        conversation_id,
+        user_id
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .unwrap_or(false);
+
+    if is_member {
+        Ok(())
+    } else {
+        Err(AppError::JsendFail(
+            json!({"conversation" : "conversation does not exist"}),
+        ))
+    }
+}
+
+fn parse_conversation_id(conversation_id: &str) -> Result<Uuid, AppError> {
+    Uuid::parse_str(conversation_id)
+        .map_err(|_| AppError::JsendFail(json!({"conversation_id" : "not a valid UUID"})))
+}
+
+/// Messages after the given sequence number, oldest first.
+async fn messages_after(
+    data: &AppState,
+    conversation_id: Uuid,
+    after_seq: i64,
+) -> Result<Vec<MessageResponse>, AppError> {
+    sqlx::query_as!(
+        MessageResponse,
+        r#"SELECT
+            messages.id,
+            messages.seq,
+            messages.conversation_id,
+            messages.sender_id,
+            users.username,
+            profiles.display_name,
+            profiles.profile_image,
+            messages.content,
+            ARRAY(
+                SELECT readers.username FROM conversation_members
+                JOIN users AS readers ON readers.id = conversation_members.user_id
+                WHERE conversation_members.conversation_id = messages.conversation_id
+                    AND conversation_members.user_id <> messages.sender_id
+                    AND conversation_members.last_read_seq >= messages.seq
+            ) AS "read_by!",
+            messages.created_at
+        FROM messages
+        JOIN users ON users.id = messages.sender_id
+        JOIN profiles ON profiles.user_id = users.id
+        WHERE messages.conversation_id = $1 AND messages.seq > $2
+        ORDER BY messages.seq
+        LIMIT 100"#,
+        conversation_id,
+        after_seq
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)
+}
+
+pub async fn create_conversation(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppJson(body): AppJson<CreateConversationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    body.validate()?;
+    let user_id = user.id.ok_or(AppError::InternalServerError)?;
+
+    let mut members: Vec<Uuid> = Vec::new();
+    for username in &body.usernames {
+        let member_id = find_user_id(&data, username).await?;
+        if is_blocked(&data, Some(user_id), member_id).await? {
+            return Err(AppError::JsendFail(
+                json!({"usernames" : format!("you cannot message {}", username)}),
+            ));
+        }
+        if member_id != user_id && !members.contains(&member_id) {
+            members.push(member_id);
+        }
+    }
+    if members.is_empty() {
+        return Err(AppError::JsendFail(
+            json!({"usernames" : "you cannot message yourself"}),
+        ));
+    }
+    let is_group = members.len() > 1 || body.title.is_some();
+
+    // a 1:1 conversation is reused instead of starting another one
+    if !is_group {
+        let existing = sqlx::query_scalar!(
+            "SELECT conversations.id FROM conversations
+            WHERE NOT conversations.is_group
+                AND EXISTS (SELECT 1 FROM conversation_members WHERE conversation_id = conversations.id AND user_id = $1)
+                AND EXISTS (SELECT 1 FROM conversation_members WHERE conversation_id = conversations.id AND user_id = $2)",
+            user_id,
+            members[0]
+        )
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+        if let Some(conversation_id) = existing {
+            let response = JsendResponse::success(Some(json!({
+                "conversation_id" : conversation_id
+            })));
+            return Ok(Json(response));
+        }
+    }
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let conversation_id = sqlx::query_scalar!(
+        "INSERT INTO conversations (created_by, title, is_group) VALUES ($1, $2, $3) RETURNING id",
+        user_id,
+        body.title.as_deref().map(str::trim),
+        is_group
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    members.push(user_id);
+    sqlx::query!(
+        "INSERT INTO conversation_members (conversation_id, user_id) SELECT $1, * FROM UNNEST($2::uuid[])",
+        conversation_id,
+        &members
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "conversation_id" : conversation_id
+    })));
+    Ok(Json(response))
+}
+
+pub async fn get_conversations(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let conversations = sqlx::query_as!(
+        ConversationResponse,
+        r#"SELECT
+            conversations.id,
+            conversations.title,
+            conversations.is_group,
+            ARRAY(
+                SELECT users.username FROM conversation_members AS others
+                JOIN users ON users.id = others.user_id
+                WHERE others.conversation_id = conversations.id AND others.user_id <> $1
+                ORDER BY others.joined_at
+            ) AS "members!",
+            last_message.content AS "last_message?",
+            last_message.created_at AS "last_message_at?",
+            (
+                SELECT COUNT(*) FROM messages
+                WHERE messages.conversation_id = conversations.id
+                    AND messages.seq > conversation_members.last_read_seq
+                    AND messages.sender_id <> $1
+            ) AS "unread_count!",
+            conversations.created_at,
+            conversations.updated_at
+        FROM conversation_members
+        JOIN conversations ON conversations.id = conversation_members.conversation_id
+        LEFT JOIN LATERAL (
+            SELECT content, created_at FROM messages
+            WHERE messages.conversation_id = conversations.id
+            ORDER BY seq DESC
+            LIMIT 1
+        ) AS last_message ON TRUE
+        WHERE conversation_members.user_id = $1
+        ORDER BY conversations.updated_at DESC
+        LIMIT $2 OFFSET $3"#,
+        user.id,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "conversations" : conversations,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
+
+/// Message history, newest first.
+pub async fn get_messages(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(conversation_id): AppPath<String>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let conversation_id = parse_conversation_id(&conversation_id)?;
+    ensure_member(&data, conversation_id, user.id).await?;
+
+    let messages = sqlx::query_as!(
+        MessageResponse,
+        r#"SELECT
+            messages.id,
+            messages.seq,
+            messages.conversation_id,
+            messages.sender_id,
+            users.username,
+            profiles.display_name,
+            profiles.profile_image,
+            messages.content,
+            ARRAY(
+                SELECT readers.username FROM conversation_members
+                JOIN users AS readers ON readers.id = conversation_members.user_id
+                WHERE conversation_members.conversation_id = messages.conversation_id
+                    AND conversation_members.user_id <> messages.sender_id
+                    AND conversation_members.last_read_seq >= messages.seq
+            ) AS "read_by!",
+            messages.created_at
+        FROM messages
+        JOIN users ON users.id = messages.sender_id
+        JOIN profiles ON profiles.user_id = users.id
+        WHERE messages.conversation_id = $1
+        ORDER BY messages.seq DESC
+        LIMIT $2 OFFSET $3"#,
+        conversation_id,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "messages" : messages,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
+
+/// Members of the conversation, the only users its events are pushed to.
+async fn member_audience<'e>(
+    db: impl PgExecutor<'e>,
+    conversation_id: Uuid,
+) -> Result<Audience, AppError> {
+    let members = sqlx::query_scalar!(
+        "SELECT user_id FROM conversation_members WHERE conversation_id = $1",
+        conversation_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    Ok(Audience {
+        only: Some(members),
+        except: Vec::new(),
+    })
+}
+
+struct InsertedMessage {
+    id: Uuid,
+    seq: i64,
+}
+
+async fn insert_message(
+    tx: &mut Transaction<'_, Postgres>,
+    conversation_id: Uuid,
+    sender_id: Uuid,
+    content: &str,
+) -> Result<InsertedMessage, AppError> {
+    // locks the conversation row before taking a sequence number, so messages of
+    // a conversation commit in seq order and streams never skip a late commit
+    sqlx::query!(
+        "UPDATE conversations SET updated_at = NOW() WHERE id = $1",
+        conversation_id
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let message = sqlx::query_as!(
+        InsertedMessage,
+        "INSERT INTO messages (conversation_id, sender_id, content) VALUES ($1, $2, $3) RETURNING id, seq",
+        conversation_id,
+        sender_id,
+        content
+    )
+    .fetch_one(&mut **tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    // the sender has obviously read everything up to their own message
+    sqlx::query!(
+        "UPDATE conversation_members SET last_read_seq = $1 WHERE conversation_id = $2 AND user_id = $3",
+        message.seq,
+        conversation_id,
+        sender_id
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    Ok(message)
+}
+
+pub async fn send_message(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(conversation_id): AppPath<String>,
+    AppJson(body): AppJson<MessageSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    body.validate()?;
+    let conversation_id = parse_conversation_id(&conversation_id)?;
+    let user_id = user.id.ok_or(AppError::InternalServerError)?;
+    ensure_member(&data, conversation_id, user.id).await?;
+
+    // a block ends a 1:1 conversation, groups carry on
+    let blocked: bool = sqlx::query_scalar!(
+        "SELECT EXISTS (
+            SELECT 1 FROM conversations
+            JOIN conversation_members AS other ON other.conversation_id = conversations.id AND other.user_id <> $2
+            JOIN blocks ON (blocks.blocker_id = $2 AND blocks.blocked_id = other.user_id)
+                OR (blocks.blocker_id = other.user_id AND blocks.blocked_id = $2)
+            WHERE conversations.id = $1 AND NOT conversations.is_group
+        )",
+        conversation_id,
+        user_id
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .unwrap_or(false);
+    if blocked {
+        return Err(AppError::JsendFail(
+            json!({"authorization" : "you cannot message this user"}),
+        ));
+    }
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let message = insert_message(&mut tx, conversation_id, user_id, &body.content).await?;
+    let audience = member_audience(&mut *tx, conversation_id).await?;
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    data.realtime
+        .publish_to(
+            RealtimeEvent::Message {
+                conversation_id,
+                seq: message.seq,
+                sender_id: user_id,
+            },
+            audience,
+        )
+        .await;
+
+    let response = JsendResponse::success(Some(json!({
+        "message_id" : message.id,
+        "seq" : message.seq,
+    })));
+    Ok(Json(response))
+}
+
+/// Marks every message in the conversation as read, which other members see
+/// as a read receipt.
+pub async fn mark_conversation_read(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(conversation_id): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let conversation_id = parse_conversation_id(&conversation_id)?;
+    let user_id = user.id.ok_or(AppError::InternalServerError)?;
+
+    let seq = sqlx::query_scalar!(
+        "UPDATE conversation_members
+        SET last_read_seq = GREATEST(last_read_seq, (SELECT COALESCE(MAX(seq), 0) FROM messages WHERE conversation_id = $1))
+        WHERE conversation_id = $1 AND user_id = $2
+        RETURNING last_read_seq",
+        conversation_id,
+        user_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or_else(|| {
+        AppError::JsendFail(json!({"conversation" : "conversation does not exist"}))
+    })?;
+
+    let audience = member_audience(&data.db, conversation_id).await?;
+    data.realtime
+        .publish_to(
+            RealtimeEvent::MessagesRead {
+                conversation_id,
+                user_id,
+                seq,
+            },
+            audience,
+        )
+        .await;
+
+    let response = JsendResponse::success(Some(json!({"last_read_seq" : seq})));
+    Ok(Json(response))
+}
+
+pub async fn leave_conversation(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(conversation_id): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let conversation_id = parse_conversation_id(&conversation_id)?;
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let left = sqlx::query!(
+        "DELETE FROM conversation_members WHERE conversation_id = $1 AND user_id = $2",
+        conversation_id,
+        user.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    if left.rows_affected() == 0 {
+        return Err(AppError::JsendFail(
+            json!({"conversation" : "conversation does not exist"}),
+        ));
+    }
+
+    // the history goes once the last member has left
+    sqlx::query!(
+        "DELETE FROM conversations WHERE id = $1
+        AND NOT EXISTS (SELECT 1 FROM conversation_members WHERE conversation_id = $1)",
+        conversation_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+/// Streams new messages as server-sent events with the message sequence number
+/// as event id, plus `read` events for read receipts. Reconnecting with
+/// `Last-Event-ID` replays the messages sent in between.
+pub async fn stream_messages(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    headers: HeaderMap,
+    AppPath(conversation_id): AppPath<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let conversation_id = parse_conversation_id(&conversation_id)?;
+    ensure_member(&data, conversation_id, user.id).await?;
+
+    // subscribe before reading the latest message so nothing is missed
+    let mut live = data.realtime.subscribe();
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i64>().ok());
+    let mut last_sent = match last_event_id {
+        Some(last_event_id) => last_event_id,
+        None => sqlx::query_scalar!(
+            "SELECT COALESCE(MAX(seq), 0) FROM messages WHERE conversation_id = $1",
+            conversation_id
+        )
+        .fetch_one(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .unwrap_or(0),
+    };
+
+    let (sender, receiver) = mpsc::channel::<Event>(64);
+    tokio::spawn(async move {
+        // the stream ends with the session it was opened with
+        let Some(user_id) = user.id else {
+            return;
+        };
+        if session_ended(&data, user_id, user.session_epoch)
+            .await
+            .unwrap_or(true)
+        {
+            return;
+        }
+        let mut pending = last_event_id.is_some();
+        loop {
+            if pending {
+                // the stream only stays open while the user is still a member
+                if ensure_member(&data, conversation_id, user.id)
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+                let Ok(messages) = messages_after(&data, conversation_id, last_sent).await else {
+                    return;
+                };
+                pending = messages.len() == 100;
+                for message in messages {
+                    last_sent = message.seq;
+                    let event = Event::default()
+                        .id(message.seq.to_string())
+                        .event("message")
+                        .json_data(&message);
+                    let Ok(event) = event else {
+                        continue;
+                    };
+                    if sender.send(event).await.is_err() {
+                        return;
+                    }
+                }
+                if pending {
+                    continue;
+                }
+            }
+
+            // stops as soon as the client disconnects instead of on the next event
+            let event = tokio::select! {
+                _ = sender.closed() => return,
+                event = live.recv() => event,
+            };
+            match event.map(|envelope| envelope.event) {
+                Ok(RealtimeEvent::Message {
+                    conversation_id: id,
+                    seq,
+                    ..
+                }) if id == conversation_id && seq > last_sent => pending = true,
+                Ok(RealtimeEvent::MessagesRead {
+                    conversation_id: id,
+                    user_id,
+                    seq,
+                }) if id == conversation_id => {
+                    let event = Event::default()
+                        .event("read")
+                        .json_data(json!({"user_id" : user_id, "seq" : seq}));
+                    if let Ok(event) = event {
+                        if sender.send(event).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                Ok(event) if event.ends_session(user_id, user.session_epoch) => return,
+                // catch up from the database after missing events
+                Err(RecvError::Lagged(_)) => pending = true,
+                Err(RecvError::Closed) => return,
+                Ok(_) => {}
+            }
+        }
+    });
+
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|event| (Ok(event), receiver))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::auth_handlers::create_user_with_profile;
+    use sqlx::PgPool;
+    use std::time::Duration;
+
+    #[sqlx::test]
+    async fn messages_of_a_conversation_commit_in_seq_order(db: PgPool) {
+        let mut tx = db.begin().await.unwrap();
+        let user_id = create_user_with_profile(&mut tx, "sender", "sender@example.com", None)
+            .await
+            .unwrap();
+        let conversation_id = sqlx::query_scalar!(
+            "INSERT INTO conversations (created_by) VALUES ($1) RETURNING id",
+            user_id
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap();
+        sqlx::query!(
+            "INSERT INTO conversation_members (conversation_id, user_id) VALUES ($1, $2)",
+            conversation_id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        let mut first = db.begin().await.unwrap();
+        let first_message = insert_message(&mut first, conversation_id, user_id, "first")
+            .await
+            .unwrap();
+
+        let second = std::thread::spawn({
+            let db = db.clone();
+            move || {
+                tokio::runtime::Runtime::new()
+                    .unwrap()
+                    .block_on(async move {
+                        let mut tx = db.begin().await.unwrap();
+                        let message = insert_message(&mut tx, conversation_id, user_id, "second")
+                            .await
+                            .unwrap();
+                        tx.commit().await.unwrap();
+                        message
+                    })
+            }
+        });
+
+        // the second message waits for the first instead of committing a higher seq ahead of it
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(!second.is_finished());
+        first.commit().await.unwrap();
+        let second_message = second.join().unwrap();
+        assert!(second_message.seq > first_message.seq);
+    }
+
+    #[sqlx::test]
+    async fn message_events_go_to_the_members_only(db: PgPool) {
+        let mut tx = db.begin().await.unwrap();
+        let member = create_user_with_profile(&mut tx, "member", "member@example.com", None)
+            .await
+            .unwrap();
+        let outsider = create_user_with_profile(&mut tx, "outsider", "outsider@example.com", None)
+            .await
+            .unwrap();
+        let conversation_id = sqlx::query_scalar!(
+            "INSERT INTO conversations (created_by) VALUES ($1) RETURNING id",
+            member
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap();
+        sqlx::query!(
+            "INSERT INTO conversation_members (conversation_id, user_id) VALUES ($1, $2)",
+            conversation_id,
+            member
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        let audience = member_audience(&db, conversation_id).await.unwrap();
+        assert!(audience.includes(member));
+        assert!(!audience.includes(outsider));
+    }
+}
@@ -0,0 +1,349 @@
+use crate::{
+    handlers::{block_handlers::is_blocked, user_handlers::find_user_id},
+    model::{ProfileResponse, UserModel},
+    notifications::{notify, NotificationKind},
+    realtime::RealtimeEvent,
+    response::{AppError, AppPath, AppQuery, JsendResponse},
+    schema::PaginationSchema,
+    session_auth::current_user_id,
+    AppState,
+};
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+use serde_json::json;
+use std::sync::Arc;
+use tower_sessions::Session;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Whether the viewer may see posts by the author. Posts of private accounts are
+/// only visible to the author and their approved followers.
+pub async fn can_view_posts(
+    data: &AppState,
+    viewer: Option<Uuid>,
+    author: Uuid,
+) -> Result<bool, AppError> {
+    sqlx::query_scalar!(
+        "SELECT NOT profiles.is_private
+            OR profiles.user_id = $1
+            OR EXISTS (SELECT 1 FROM follows WHERE follower_id = $1 AND followee_id = profiles.user_id)
+        FROM profiles WHERE profiles.user_id = $2",
+        viewer,
+        author
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)
+    .map(|visible| visible.flatten().unwrap_or(false))
+}
+
+pub async fn follow_user(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(username): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let followee_id = find_user_id(&data, &username).await?;
+    if user.id == Some(followee_id) {
+        return Err(AppError::JsendFail(
+            json!({"follow" : "you cannot follow yourself"}),
+        ));
+    }
+    if is_blocked(&data, user.id, followee_id).await? {
+        return Err(AppError::JsendFail(
+            json!({"follow" : "you cannot follow this user"}),
+        ));
+    }
+
+    let is_private = sqlx::query_scalar!(
+        "SELECT is_private FROM profiles WHERE user_id = $1",
+        followee_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .unwrap_or(false);
+    let already_following: bool = sqlx::query_scalar!(
+        "SELECT EXISTS (SELECT 1 FROM follows WHERE follower_id = $1 AND followee_id = $2)",
+        user.id,
+        followee_id
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .unwrap_or(false);
+
+    // private accounts have to approve new followers first
+    let (status, kind) = if is_private && !already_following {
+        let requested = sqlx::query!(
+            "INSERT INTO follow_requests (requester_id, target_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            user.id,
+            followee_id
+        )
+        .execute(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+        ("requested", (requested.rows_affected() > 0).then_some(NotificationKind::FollowRequest))
+    } else {
+        let followed = sqlx::query!(
+            "INSERT INTO follows (follower_id, followee_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            user.id,
+            followee_id
+        )
+        .execute(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+        ("following", (followed.rows_affected() > 0).then_some(NotificationKind::Follow))
+    };
+
+    if let (Some(kind), Some(user_id)) = (kind, user.id) {
+        if notify(&data.db, followee_id, user_id, kind, None, None).await? {
+            data.realtime
+                .publish(RealtimeEvent::notification(followee_id, kind))
+                .await;
+        }
+    }
+
+    let response = JsendResponse::success(Some(json!({"status" : status})));
+    Ok(Json(response))
+}
+
+pub async fn unfollow_user(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(username): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let followee_id = find_user_id(&data, &username).await?;
+    sqlx::query!(
+        "DELETE FROM follows WHERE follower_id = $1 AND followee_id = $2",
+        user.id,
+        followee_id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    // also withdraws a request that was never answered
+    sqlx::query!(
+        "DELETE FROM follow_requests WHERE requester_id = $1 AND target_id = $2",
+        user.id,
+        followee_id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    // posts of a private account are no longer visible
+    data.realtime
+        .publish(RealtimeEvent::VisibilityChanged {
+            user_ids: vec![followee_id],
+        })
+        .await;
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+pub async fn get_follow_requests(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let requests = sqlx::query_as!(
+        ProfileResponse,
+        "SELECT profiles.id AS profile_id, profiles.profile_image, users.username, profiles.display_name, profiles.bio, profiles.website, profiles.location, profiles.pronouns
+        FROM follow_requests
+        JOIN users ON users.id = follow_requests.requester_id
+        JOIN profiles ON profiles.user_id = users.id
+        WHERE follow_requests.target_id = $1
+        ORDER BY follow_requests.created_at DESC
+        LIMIT $2 OFFSET $3",
+        user.id,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "requests" : requests,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
+
+pub async fn approve_follow_request(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(username): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let requester_id = find_user_id(&data, &username).await?;
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    // the request decides whether there was anything to approve, an existing
+    // follow must not turn a missing request into a success or vice versa
+    sqlx::query!(
+        "DELETE FROM follow_requests WHERE requester_id = $1 AND target_id = $2
+        RETURNING requester_id",
+        requester_id,
+        user.id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or_else(|| {
+        AppError::JsendFail(json!({"follow_request" : "follow request does not exist"}))
+    })?;
+
+    let followed = sqlx::query!(
+        "INSERT INTO follows (follower_id, followee_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        requester_id,
+        user.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    // the same notification as following a public account
+    let user_id = user.id.ok_or(AppError::InternalServerError)?;
+    let kind = NotificationKind::Follow;
+    if followed.rows_affected() > 0
+        && notify(&data.db, user_id, requester_id, kind, None, None).await?
+    {
+        data.realtime
+            .publish(RealtimeEvent::notification(user_id, kind))
+            .await;
+    }
+    // posts of the private account are visible to the new follower
+    data.realtime
+        .publish(RealtimeEvent::VisibilityChanged {
+            user_ids: vec![user_id],
+        })
+        .await;
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+pub async fn deny_follow_request(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(username): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let requester_id = find_user_id(&data, &username).await?;
+    let denied = sqlx::query!(
+        "DELETE FROM follow_requests WHERE requester_id = $1 AND target_id = $2",
+        requester_id,
+        user.id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    if denied.rows_affected() == 0 {
+        return Err(AppError::JsendFail(
+            json!({"follow_request" : "follow request does not exist"}),
+        ));
+    }
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+/// Private accounts only show their connections to those who may see their posts.
+pub async fn get_followers(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    AppPath(username): AppPath<String>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let user_id = find_user_id(&data, &username).await?;
+    let viewer = current_user_id(&session, &data).await?;
+    if is_blocked(&data, viewer, user_id).await? {
+        return Err(AppError::JsendFail(
+            json!({"username" : "user does not exist"}),
+        ));
+    }
+    if !can_view_posts(&data, viewer, user_id).await? {
+        return Err(AppError::JsendFail(
+            json!({"username" : "this account is private"}),
+        ));
+    }
+    let followers = sqlx::query_as!(
+        ProfileResponse,
+        "SELECT profiles.id AS profile_id, profiles.profile_image, users.username, profiles.display_name, profiles.bio, profiles.website, profiles.location, profiles.pronouns
+        FROM follows
+        JOIN users ON users.id = follows.follower_id
+        JOIN profiles ON profiles.user_id = users.id
+        WHERE follows.followee_id = $1
+        ORDER BY follows.created_at DESC
+        LIMIT $2 OFFSET $3",
+        user_id,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "followers" : followers,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
+
+/// Private accounts only show their connections to those who may see their posts.
+pub async fn get_following(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    AppPath(username): AppPath<String>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let user_id = find_user_id(&data, &username).await?;
+    let viewer = current_user_id(&session, &data).await?;
+    if is_blocked(&data, viewer, user_id).await? {
+        return Err(AppError::JsendFail(
+            json!({"username" : "user does not exist"}),
+        ));
+    }
+    if !can_view_posts(&data, viewer, user_id).await? {
+        return Err(AppError::JsendFail(
+            json!({"username" : "this account is private"}),
+        ));
+    }
+    let following = sqlx::query_as!(
+        ProfileResponse,
+        "SELECT profiles.id AS profile_id, profiles.profile_image, users.username, profiles.display_name, profiles.bio, profiles.website, profiles.location, profiles.pronouns
+        FROM follows
+        JOIN users ON users.id = follows.followee_id
+        JOIN profiles ON profiles.user_id = users.id
+        WHERE follows.follower_id = $1
+        ORDER BY follows.created_at DESC
+        LIMIT $2 OFFSET $3",
+        user_id,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "following" : following,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
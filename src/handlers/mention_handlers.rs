@@ -0,0 +1,66 @@
+use crate::{
+    model::{ContentHtml, MentionResponse, UserModel},
+    response::{AppError, AppQuery, JsendResponse},
+    schema::PaginationSchema,
+    AppState,
+};
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+use serde_json::json;
+use sqlx::types::Json as SqlJson;
+use std::sync::Arc;
+use validator::Validate;
+
+/// Posts and comments mentioning the current user, newest first. Mentions by
+/// blocked or muted users and in posts the user can no longer see are left out.
+pub async fn get_mentions(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let mentions = sqlx::query_as!(
+        MentionResponse,
+        r#"SELECT
+            mentions.id,
+            mentions.post_id,
+            mentions.comment_id,
+            mentions.author_id,
+            users.username,
+            profiles.display_name,
+            profiles.profile_image,
+            COALESCE(comments.content, posts.content) AS "content!",
+            content_with_mentions(COALESCE(comments.content, posts.content), mentions.post_id, mentions.comment_id) AS "content_html!: SqlJson<ContentHtml>",
+            mentions.created_at
+        FROM mentions
+        JOIN users ON users.id = mentions.author_id
+        JOIN profiles ON profiles.user_id = mentions.author_id
+        JOIN posts ON posts.id = mentions.post_id
+        JOIN profiles AS post_authors ON post_authors.user_id = posts.user_id
+        LEFT JOIN comments ON comments.id = mentions.comment_id
+        WHERE mentions.user_id = $1
+            AND NOT EXISTS (
+                SELECT 1 FROM blocks
+                WHERE (blocker_id = $1 AND blocked_id IN (mentions.author_id, posts.user_id))
+                    OR (blocked_id = $1 AND blocker_id IN (mentions.author_id, posts.user_id))
+            )
+            AND NOT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $1 AND muted_id = mentions.author_id)
+            AND (NOT post_authors.is_private
+                OR posts.user_id = $1
+                OR EXISTS (SELECT 1 FROM follows WHERE follower_id = $1 AND followee_id = posts.user_id))
+        ORDER BY mentions.created_at DESC
+        LIMIT $2 OFFSET $3"#,
+        user.id,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "mentions" : mentions,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
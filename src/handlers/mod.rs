@@ -1,6 +1,19 @@
+pub mod account_handlers;
 pub mod auth_handlers;
+pub mod block_handlers;
+pub mod bookmark_handlers;
 pub mod comment_handlers;
+pub mod community_handlers;
+pub mod conversation_handlers;
 pub mod error_handlers;
+pub mod follow_handlers;
+pub mod mention_handlers;
+pub mod notification_handlers;
+pub mod oidc_handlers;
+pub mod passkey_handlers;
 pub mod post_handlers;
 pub mod profile_handlers;
+pub mod realtime_handlers;
+pub mod search_handlers;
+pub mod tag_handlers;
 pub mod user_handlers;
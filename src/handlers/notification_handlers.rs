@@ -0,0 +1,121 @@
+use crate::{
+    model::{NotificationResponse, UserModel},
+    response::{AppError, AppPath, AppQuery, JsendResponse},
+    schema::PaginationSchema,
+    AppState,
+};
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Newest notifications first, batched events are ordered by their latest
+/// activity. Actors the user has blocked or been blocked by are not listed.
+pub async fn get_notifications(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let notifications = sqlx::query_as!(
+        NotificationResponse,
+        r#"SELECT
+            notifications.id,
+            notifications.kind,
+            notifications.post_id,
+            notifications.comment_id,
+            COUNT(*) AS "actor_count!",
+            (ARRAY_AGG(users.username ORDER BY notification_actors.created_at DESC))[1:3] AS "actors!: Vec<String>",
+            notifications.read_at,
+            notifications.created_at,
+            notifications.updated_at
+        FROM notifications
+        JOIN notification_actors ON notification_actors.notification_id = notifications.id
+        JOIN users ON users.id = notification_actors.actor_id
+        WHERE notifications.user_id = $1
+            AND NOT EXISTS (
+                SELECT 1 FROM blocks
+                WHERE (blocker_id = $1 AND blocked_id = users.id)
+                    OR (blocker_id = users.id AND blocked_id = $1)
+            )
+        GROUP BY notifications.id
+        ORDER BY notifications.updated_at DESC
+        LIMIT $2 OFFSET $3"#,
+        user.id,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let unread_count = sqlx::query_scalar!(
+        "SELECT COUNT(DISTINCT notifications.id)
+        FROM notifications
+        JOIN notification_actors ON notification_actors.notification_id = notifications.id
+        WHERE notifications.user_id = $1
+            AND notifications.read_at IS NULL
+            AND NOT EXISTS (
+                SELECT 1 FROM blocks
+                WHERE (blocker_id = $1 AND blocked_id = notification_actors.actor_id)
+                    OR (blocker_id = notification_actors.actor_id AND blocked_id = $1)
+            )",
+        user.id
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .unwrap_or(0);
+
+    let response = JsendResponse::success(Some(json!({
+        "notifications" : notifications,
+        "unread_count" : unread_count,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
+
+pub async fn mark_notification_read(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(notification_id): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let notification_id = Uuid::parse_str(&notification_id)
+        .map_err(|_| AppError::JsendFail(json!({"notification_id" : "not a valid UUID"})))?;
+
+    let updated = sqlx::query!(
+        "UPDATE notifications SET read_at = COALESCE(read_at, NOW()) WHERE id = $1 AND user_id = $2",
+        notification_id,
+        user.id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    if updated.rows_affected() == 0 {
+        return Err(AppError::JsendFail(
+            json!({"notification" : "notification does not exist"}),
+        ));
+    }
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+pub async fn mark_all_notifications_read(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+) -> Result<impl IntoResponse, AppError> {
+    sqlx::query!(
+        "UPDATE notifications SET read_at = NOW() WHERE user_id = $1 AND read_at IS NULL",
+        user.id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
@@ -0,0 +1,359 @@
+use crate::{
+    config::OidcConfig,
+    handlers::auth_handlers::{create_user_with_profile, start_session},
+    oidc::{self, UserInfo},
+    response::{AppError, JsendResponse},
+    session_auth::current_user_id,
+    tokens::random_token,
+    AppState,
+};
+use axum::{
+    extract::{Query, State},
+    response::{IntoResponse, Redirect},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use std::sync::Arc;
+use tower_sessions::Session;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct CallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+fn oidc_config(data: &AppState) -> Result<&OidcConfig, AppError> {
+    data.env
+        .oidc
+        .as_ref()
+        .ok_or_else(|| AppError::JsendFail(json!({"oidc" : "social login is not configured"})))
+}
+
+pub async fn oidc_login_handler(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let config = oidc_config(&data)?;
+    let metadata = oidc::discover(&data.http, config).await?;
+
+    let state = random_token();
+    let nonce = random_token();
+    let verifier = random_token();
+    let url = oidc::authorization_url(&metadata, config, &state, &nonce, &verifier)?;
+
+    session
+        .insert("oidc_state", state)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    session
+        .insert("oidc_nonce", nonce)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    session
+        .insert("oidc_verifier", verifier)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    Ok(Redirect::to(&url))
+}
+
+pub async fn oidc_callback_handler(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    Query(query): Query<CallbackQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let config = oidc_config(&data)?;
+    if let Some(error) = query.error {
+        return Err(AppError::JsendFail(json!({"oidc" : error})));
+    }
+
+    // the state, nonce and verifier are single use
+    let expected_state = session
+        .remove::<String>("oidc_state")
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    let nonce = session
+        .remove::<String>("oidc_nonce")
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    let verifier = session
+        .remove::<String>("oidc_verifier")
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let (code, nonce, verifier) = login_attempt(query, expected_state, nonce, verifier)?;
+
+    let metadata = oidc::discover(&data.http, config).await?;
+    let info =
+        oidc::fetch_user_info(&data.http, &metadata, config, &code, &nonce, &verifier).await?;
+
+    let current_user = current_user_id(&session, &data).await?;
+    let user_id = resolve_identity(&data.db, config, &info, current_user).await?;
+
+    start_session(&session, &data, Some(user_id)).await?;
+
+    let username = sqlx::query_scalar!("SELECT username FROM users WHERE id = $1", user_id)
+        .fetch_one(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "username" : username
+    })));
+    Ok(Json(response))
+}
+
+/// Matches the callback against the login attempt stored in the session,
+/// returning the code, nonce and PKCE verifier to redeem it with.
+fn login_attempt(
+    query: CallbackQuery,
+    expected_state: Option<String>,
+    nonce: Option<String>,
+    verifier: Option<String>,
+) -> Result<(String, String, String), AppError> {
+    match (query.code, query.state, expected_state, nonce, verifier) {
+        (Some(code), Some(state), Some(expected), Some(nonce), Some(verifier))
+            if state == expected =>
+        {
+            Ok((code, nonce, verifier))
+        }
+        _ => Err(AppError::JsendFail(
+            json!({"oidc" : "invalid or expired login attempt"}),
+        )),
+    }
+}
+
+/// Finds the local user for an external identity, linking or creating one when
+/// the identity has not been seen before.
+async fn resolve_identity(
+    db: &PgPool,
+    config: &OidcConfig,
+    info: &UserInfo,
+    current_user: Option<Uuid>,
+) -> Result<Uuid, AppError> {
+    let linked = sqlx::query_scalar!(
+        "SELECT user_id FROM user_identities WHERE issuer = $1 AND subject = $2",
+        config.issuer_url,
+        info.sub
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    if let Some(user_id) = linked {
+        return Ok(user_id);
+    }
+
+    let email = info.email.as_ref().map(|email| email.to_ascii_lowercase());
+
+    // identities are only ever linked by the logged in owner of the account, a
+    // provider vouching for an email address is not enough to take over the
+    // local account that uses it
+    if current_user.is_none() {
+        if let Some(email) = &email {
+            let taken: bool = sqlx::query_scalar!(
+                "SELECT EXISTS (SELECT 1 FROM users WHERE email = $1)",
+                email
+            )
+            .fetch_one(db)
+            .await
+            .map_err(|_| AppError::InternalServerError)?
+            .unwrap_or(false);
+            if taken {
+                return Err(AppError::JsendFail(json!({
+                    "email" : "email already exists, log in first to link this identity"
+                })));
+            }
+        }
+    }
+
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let user_id = match current_user {
+        Some(user_id) => user_id,
+        None => {
+            let email = email.as_ref().ok_or_else(|| {
+                AppError::JsendFail(json!({"email" : "identity provider did not share an email"}))
+            })?;
+            let username = available_username(db, info).await?;
+            // the account has no password until the user sets one
+            create_user_with_profile(&mut tx, &username, email, None).await?
+        }
+    };
+
+    sqlx::query!(
+        "INSERT INTO user_identities (user_id, issuer, subject, email) VALUES ($1, $2, $3, $4)",
+        user_id,
+        config.issuer_url,
+        info.sub,
+        email
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    Ok(user_id)
+}
+
+/// Derives a username from the provider claims that satisfies the username
+/// validation rules and is not taken yet.
+async fn available_username(db: &PgPool, info: &UserInfo) -> Result<String, AppError> {
+    let source = info
+        .preferred_username
+        .as_deref()
+        .or(info.name.as_deref())
+        .or(info
+            .email
+            .as_deref()
+            .and_then(|email| email.split('@').next()))
+        .unwrap_or("user");
+    let mut base: String = source
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .take(15)
+        .collect();
+    if base.len() < 2 {
+        base = "user".to_string();
+    }
+
+    let mut candidate = base.clone();
+    for _ in 0..10 {
+        let taken: bool = sqlx::query_scalar!(
+            "SELECT EXISTS (SELECT 1 FROM users WHERE username = $1)",
+            candidate
+        )
+        .fetch_one(db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .unwrap_or(false);
+        if !taken {
+            return Ok(candidate);
+        }
+        candidate = format!("{}{}", base, &Uuid::new_v4().simple().to_string()[..4]);
+    }
+    Err(AppError::InternalServerError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> OidcConfig {
+        OidcConfig {
+            issuer_url: "http://provider.example".to_string(),
+            client_id: "client".to_string(),
+            client_secret: None,
+            redirect_url: "http://localhost/callback".to_string(),
+            scopes: "openid email".to_string(),
+        }
+    }
+
+    fn info(sub: &str, email: &str) -> UserInfo {
+        UserInfo {
+            sub: sub.to_string(),
+            email: Some(email.to_string()),
+            preferred_username: Some("someone".to_string()),
+            name: None,
+        }
+    }
+
+    fn callback(state: &str) -> CallbackQuery {
+        CallbackQuery {
+            code: Some("code".to_string()),
+            state: Some(state.to_string()),
+            error: None,
+        }
+    }
+
+    fn stored(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn login_attempt_requires_the_stored_state() {
+        let attempt = login_attempt(
+            callback("state"),
+            stored("state"),
+            stored("nonce"),
+            stored("verifier"),
+        );
+        assert_eq!(
+            attempt.unwrap(),
+            (
+                "code".to_string(),
+                "nonce".to_string(),
+                "verifier".to_string()
+            )
+        );
+
+        let forged = login_attempt(
+            callback("forged"),
+            stored("state"),
+            stored("nonce"),
+            stored("verifier"),
+        );
+        assert!(matches!(forged, Err(AppError::JsendFail(_))));
+
+        // a replayed callback finds the single use values already removed
+        let replayed = login_attempt(callback("state"), None, None, None);
+        assert!(matches!(replayed, Err(AppError::JsendFail(_))));
+    }
+
+    #[sqlx::test]
+    async fn resolve_identity_creates_then_reuses_an_account(db: PgPool) {
+        let user_id = resolve_identity(
+            &db,
+            &config(),
+            &info("subject", "Someone@Example.com"),
+            None,
+        )
+        .await
+        .unwrap();
+        let (email, password) =
+            sqlx::query!("SELECT email, password FROM users WHERE id = $1", user_id)
+                .fetch_one(&db)
+                .await
+                .map(|row| (row.email, row.password))
+                .unwrap();
+        assert_eq!(email, "someone@example.com");
+        assert_eq!(password, None);
+
+        let again = resolve_identity(&db, &config(), &info("subject", "other@example.com"), None)
+            .await
+            .unwrap();
+        assert_eq!(again, user_id);
+    }
+
+    #[sqlx::test]
+    async fn resolve_identity_links_only_for_the_logged_in_owner(db: PgPool) {
+        let owner = resolve_identity(&db, &config(), &info("first", "someone@example.com"), None)
+            .await
+            .unwrap();
+
+        // the same email from another identity does not take over the account
+        let takeover =
+            resolve_identity(&db, &config(), &info("second", "someone@example.com"), None).await;
+        assert!(matches!(takeover, Err(AppError::JsendFail(_))));
+
+        let linked = resolve_identity(
+            &db,
+            &config(),
+            &info("second", "someone@example.com"),
+            Some(owner),
+        )
+        .await
+        .unwrap();
+        assert_eq!(linked, owner);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::{
+    handlers::auth_handlers::start_session,
+    model::{PasskeyResponse, UserModel},
+    response::{AppError, AppJson, AppPath, JsendResponse},
+    schema::{PasskeyLoginSchema, PasskeyRegisterSchema},
+    AppState,
+};
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde_json::json;
+use std::sync::Arc;
+use tower_sessions::Session;
+use uuid::Uuid;
+use validator::Validate;
+use webauthn_rs::prelude::{
+    CredentialID, Passkey, PasskeyAuthentication, PasskeyRegistration, PublicKeyCredential,
+};
+
+fn encode_credential_id(cred_id: &CredentialID) -> String {
+    URL_SAFE_NO_PAD.encode(cred_id)
+}
+
+async fn load_passkeys(data: &AppState, user_id: Uuid) -> Result<Vec<Passkey>, AppError> {
+    let rows = sqlx::query_scalar!("SELECT passkey FROM passkeys WHERE user_id = $1", user_id)
+        .fetch_all(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    rows.iter()
+        .map(|row| serde_json::from_str::<Passkey>(row).map_err(|_| AppError::InternalServerError))
+        .collect()
+}
+
+pub async fn passkey_register_start_handler(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id = user.id.ok_or(AppError::InternalServerError)?;
+    let exclude: Vec<CredentialID> = load_passkeys(&data, user_id)
+        .await?
+        .iter()
+        .map(|passkey| passkey.cred_id().clone())
+        .collect();
+
+    let (options, registration) = data
+        .webauthn
+        .start_passkey_registration(user_id, &user.username, &user.username, Some(exclude))
+        .map_err(|_| AppError::InternalServerError)?;
+
+    session
+        .insert("passkey_registration", registration)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "options" : options
+    })));
+    Ok(Json(response))
+}
+
+pub async fn passkey_register_finish_handler(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppJson(body): AppJson<PasskeyRegisterSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    body.validate()?;
+    let registration = session
+        .remove::<PasskeyRegistration>("passkey_registration")
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .ok_or_else(|| AppError::JsendFail(json!({"passkey" : "no registration in progress"})))?;
+
+    let passkey = data
+        .webauthn
+        .finish_passkey_registration(&body.credential, &registration)
+        .map_err(|_| AppError::JsendFail(json!({"passkey" : "passkey could not be verified"})))?;
+
+    let serialized = serde_json::to_string(&passkey).map_err(|_| AppError::InternalServerError)?;
+    let passkey_id = sqlx::query_scalar!(
+        "INSERT INTO passkeys (user_id, credential_id, name, passkey) VALUES ($1, $2, $3, $4) RETURNING id",
+        user.id,
+        encode_credential_id(passkey.cred_id()),
+        body.name,
+        serialized
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "passkey_id" : passkey_id
+    })));
+    Ok(Json(response))
+}
+
+pub async fn passkey_login_start_handler(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    AppJson(body): AppJson<PasskeyLoginSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    body.validate()?;
+    let user_id = sqlx::query_scalar!("SELECT id FROM users WHERE username = $1", body.username)
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .ok_or_else(|| AppError::JsendFail(json!({"username" : "user does not exist"})))?;
+
+    let passkeys = load_passkeys(&data, user_id).await?;
+    if passkeys.is_empty() {
+        return Err(AppError::JsendFail(
+            json!({"passkey" : "user has no passkeys registered"}),
+        ));
+    }
+
+    let (options, authentication) = data
+        .webauthn
+        .start_passkey_authentication(&passkeys)
+        .map_err(|_| AppError::InternalServerError)?;
+
+    session
+        .insert("passkey_authentication", (user_id, authentication))
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "options" : options
+    })));
+    Ok(Json(response))
+}
+
+pub async fn passkey_login_finish_handler(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    AppJson(credential): AppJson<PublicKeyCredential>,
+) -> Result<impl IntoResponse, AppError> {
+    let (user_id, authentication) = session
+        .remove::<(Uuid, PasskeyAuthentication)>("passkey_authentication")
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .ok_or_else(|| AppError::JsendFail(json!({"passkey" : "no login in progress"})))?;
+
+    let result = data
+        .webauthn
+        .finish_passkey_authentication(&credential, &authentication)
+        .map_err(|_| AppError::JsendFail(json!({"passkey" : "passkey could not be verified"})))?;
+
+    let row = sqlx::query!(
+        "SELECT id, passkey FROM passkeys WHERE credential_id = $1 AND user_id = $2",
+        encode_credential_id(result.cred_id()),
+        user_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or_else(|| AppError::JsendFail(json!({"passkey" : "passkey does not exist"})))?;
+
+    // persist the new signature counter so cloned authenticators are detected
+    let mut passkey =
+        serde_json::from_str::<Passkey>(&row.passkey).map_err(|_| AppError::InternalServerError)?;
+    passkey.update_credential(&result);
+    let serialized = serde_json::to_string(&passkey).map_err(|_| AppError::InternalServerError)?;
+    sqlx::query!(
+        "UPDATE passkeys SET passkey = $1, last_used_at = NOW(), updated_at = NOW() WHERE id = $2",
+        serialized,
+        row.id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    start_session(&session, &data, Some(user_id)).await?;
+
+    let username = sqlx::query_scalar!("SELECT username FROM users WHERE id = $1", user_id)
+        .fetch_one(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "username" : username
+    })));
+    Ok(Json(response))
+}
+
+pub async fn get_passkeys_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+) -> Result<impl IntoResponse, AppError> {
+    let passkeys = sqlx::query_as!(
+        PasskeyResponse,
+        "SELECT id, name, last_used_at, created_at FROM passkeys WHERE user_id = $1 ORDER BY created_at DESC",
+        user.id
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "passkeys" : passkeys
+    })));
+    Ok(Json(response))
+}
+
+pub async fn delete_passkey_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppPath(passkey_id): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let passkey_id = Uuid::parse_str(&passkey_id)
+        .map_err(|_| AppError::JsendFail(json!({"passkey_id" : "not a valid UUID"})))?;
+
+    let deleted = sqlx::query!(
+        "DELETE FROM passkeys WHERE id = $1 AND user_id = $2",
+        passkey_id,
+        user.id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    if deleted.rows_affected() == 0 {
+        return Err(AppError::JsendFail(
+            json!({"passkey" : "passkey does not exist"}),
+        ));
+    }
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
@@ -1,45 +1,54 @@
 use crate::{
+    handlers::{
+        block_handlers::is_blocked,
+        community_handlers::{community_role, CommunityRole},
+        follow_handlers::can_view_posts,
+        user_handlers::find_user_id,
+    },
     model::{PostResponse, UserModel},
-    response::{AppError, AppJson, AppPath, JsendResponse},
-    schema::{PostSchema, ReactPostSchema},
+    response::{AppError, AppJson, AppPath, AppQuery, JsendResponse},
+    schema::{FeedSchema, PaginationSchema, PostSchema, PublishSchema, ReactPostSchema},
+    notifications::{notify, NotificationKind},
+    publishing::{announce, publish_post, record_publication, PostStatus},
+    realtime::RealtimeEvent,
+    session_auth::current_user_id,
+    tags::{collect_tags, save_post_tags},
     AppState,
 };
 use axum::{extract::State, response::IntoResponse, Extension, Json};
+use chrono::Utc;
 use serde_json::json;
+use sqlx::{types::Json as SqlJson, Postgres, Transaction};
 use std::sync::Arc;
+use tower_sessions::Session;
 use uuid::Uuid;
 use validator::Validate;
 
 pub async fn get_post(
+    session: Session,
     State(data): State<Arc<AppState>>,
     AppPath(postid): AppPath<String>,
 ) -> Result<impl IntoResponse, AppError> {
     let postid = Uuid::parse_str(&postid)
         .map_err(|_| AppError::JsendFail(json!({"post_id" : "not a valid UUID"})))?;
-    let post :PostResponse = sqlx::query_as!(
-        PostResponse,
-        "SELECT
-            posts.id,
-            users.username,
-            posts.title,
-            posts.content,
-            posts.created_at,
-            posts.updated_at,
-            posts.user_id,
-            profiles.profile_image,
-            COALESCE(SUM(CASE WHEN reactions.reaction_type = TRUE THEN 1 ELSE 0 END), 0) AS likes,
-            COALESCE(SUM(CASE WHEN reactions.reaction_type = FALSE THEN 1 ELSE 0 END), 0) AS dislikes
+    let viewer = current_user_id(&session, &data).await?;
+    let post: PostResponse = sqlx::query_scalar!(
+        "SELECT post_response(posts.id, $2) AS \"post!: SqlJson<PostResponse>\"
         FROM posts
-        JOIN users ON posts.user_id = users.id
-        JOIN profiles ON profiles.user_id = users.id
-        LEFT JOIN reactions ON posts.id = reactions.post_id
-        WHERE posts.id = $1
-        GROUP BY posts.id, users.username, posts.title, posts.content, posts.created_at, posts.updated_at, users.id, profiles.profile_image",postid
+        WHERE posts.id = $1 AND (posts.status = 'published' OR posts.user_id = $2)",
+        postid,
+        viewer
     )
     .fetch_optional(&data.db)
     .await
     .map_err(|_| AppError::InternalServerError)?
-    .ok_or( AppError::JsendFail(json!({"post" : "post doesnt exist"})))?;
+    .ok_or(AppError::JsendFail(json!({"post" : "post doesnt exist"})))?
+    .0;
+    if is_blocked(&data, viewer, post.user_id).await?
+        || !can_view_posts(&data, viewer, post.user_id).await?
+    {
+        return Err(AppError::JsendFail(json!({"post" : "post doesnt exist"})));
+    }
     let response = JsendResponse::success(Some(json!({
         "post": post
     })));
@@ -53,12 +62,15 @@ pub async fn delete_post(
 ) -> Result<impl IntoResponse, AppError> {
     let post_id = Uuid::parse_str(&postid)
         .map_err(|_| AppError::JsendFail(json!({"post_id" : "not a valid UUID"})))?;
-    let post_uuid = sqlx::query_scalar!("SELECT user_id FROM posts WHERE id = $1", post_id)
-        .fetch_optional(&data.db)
-        .await
-        .map_err(|_| AppError::InternalServerError)?;
+    let post = sqlx::query!(
+        "SELECT user_id, community_id FROM posts WHERE id = $1",
+        post_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
 
-    let post_uuid = match post_uuid {
+    let post = match post {
         Some(val) => val,
         None => {
             return Err(AppError::JsendFail(
@@ -67,7 +79,14 @@ pub async fn delete_post(
         }
     };
 
-    if user.id != Some(post_uuid) {
+    // community moderators can remove any post in their community
+    let is_moderator = match post.community_id {
+        Some(community_id) => community_role(&data, community_id, user.id)
+            .await?
+            .is_some_and(CommunityRole::can_moderate),
+        None => false,
+    };
+    if user.id != Some(post.user_id) && !is_moderator {
         return Err(AppError::JsendFail(
             json!({"authorization" : "user not authorized to delete this"}),
         ));
@@ -92,6 +111,22 @@ pub async fn react_to_post(
     let post_id = Uuid::parse_str(&postid)
         .map_err(|_| AppError::JsendFail(json!({"post_id" : "not a valid UUID"})))?;
 
+    let author_id = sqlx::query_scalar!(
+        "SELECT user_id FROM posts WHERE id = $1 AND status = 'published'",
+        post_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or(AppError::JsendFail(json!({"post" : "post doesnt exist"})))?;
+    if is_blocked(&data, user.id, author_id).await?
+        || !can_view_posts(&data, user.id, author_id).await?
+    {
+        return Err(AppError::JsendFail(
+            json!({"authorization" : "you cannot react to this post"}),
+        ));
+    }
+
     let existing_reaction = sqlx::query!(
         "SELECT id FROM reactions WHERE post_id = $1 AND user_id = $2",
         post_id,
@@ -101,6 +136,7 @@ pub async fn react_to_post(
     .await
     .map_err(|_| AppError::InternalServerError)?;
 
+    let inserted = existing_reaction.is_none();
     if let Some(reaction) = existing_reaction {
         // Update the existing reaction
         sqlx::query!(
@@ -124,6 +160,19 @@ pub async fn react_to_post(
         .map_err(|_| AppError::InternalServerError)?;
     }
 
+    // only new likes notify the author, nobody needs to hear about dislikes or
+    // about the same user switching their reaction back and forth
+    if inserted && is_like.is_like {
+        if let Some(user_id) = user.id {
+            let kind = NotificationKind::Like;
+            if notify(&data.db, author_id, user_id, kind, Some(post_id), None).await? {
+                data.realtime
+                    .publish(RealtimeEvent::notification(author_id, kind))
+                    .await;
+            }
+        }
+    }
+
     let counts = sqlx::query!(
         "SELECT 
             COALESCE(SUM(CASE WHEN reaction_type = TRUE THEN 1 ELSE 0 END), 0) AS likes,
@@ -136,6 +185,14 @@ pub async fn react_to_post(
     .await
     .map_err(|_| AppError::InternalServerError)?;
 
+    data.realtime
+        .publish(RealtimeEvent::Reactions {
+            post_id,
+            likes: counts.likes,
+            dislikes: counts.dislikes,
+        })
+        .await;
+
     let response = JsendResponse::success(Some(json!({
             "post_id": post_id,
             "like_count" : counts.likes,
@@ -145,27 +202,18 @@ pub async fn react_to_post(
 }
 
 pub async fn get_all_posts(
+    session: Session,
     State(data): State<Arc<AppState>>,
 ) -> Result<impl IntoResponse, AppError> {
-    let posts: Vec<PostResponse> = sqlx::query_as!(
-        PostResponse,
-        "SELECT
-            posts.id,
-            users.username,
-            posts.title,
-            posts.content,
-            posts.created_at,
-            posts.updated_at,
-            posts.user_id,
-            profiles.profile_image,
-            COALESCE(SUM(CASE WHEN reactions.reaction_type = TRUE THEN 1 ELSE 0 END), 0) AS likes,
-            COALESCE(SUM(CASE WHEN reactions.reaction_type = FALSE THEN 1 ELSE 0 END), 0) AS dislikes
+    let viewer = current_user_id(&session, &data).await?;
+    let posts: Vec<SqlJson<PostResponse>> = sqlx::query_scalar!(
+        "SELECT post_response(posts.id, $1) AS \"post!: SqlJson<PostResponse>\"
         FROM posts
-        JOIN users ON posts.user_id = users.id
-        JOIN profiles ON profiles.user_id = users.id
-        LEFT JOIN reactions ON posts.id = reactions.post_id
-        GROUP BY posts.id, users.username, posts.title, posts.content, posts.created_at, posts.updated_at, users.id, profiles.profile_image
-        ORDER BY posts.created_at DESC"
+        WHERE posts.status = 'published'
+            AND can_view_posts_of(posts.user_id, $1)
+            AND NOT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $1 AND muted_id = posts.user_id)
+        ORDER BY posts.created_at DESC",
+        viewer
     )
     .fetch_all(&data.db)
     .await
@@ -176,23 +224,638 @@ pub async fn get_all_posts(
     Ok(Json(response))
 }
 
+pub async fn get_feed(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+    AppQuery(feed): AppQuery<FeedSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let following = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM follows WHERE follower_id = $1",
+        user.id
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .unwrap_or(0);
+    let mix_popular = feed.popular && following < data.env.feed_popular_threshold;
+
+    // popular posts are the most liked of the last week
+    let posts: Vec<SqlJson<PostResponse>> = sqlx::query_scalar!(
+        "SELECT post_response(posts.id, $1) AS \"post!: SqlJson<PostResponse>\"
+        FROM posts
+        WHERE posts.status = 'published'
+            AND (posts.user_id = $1
+            OR posts.user_id IN (SELECT followee_id FROM follows WHERE follower_id = $1)
+            OR ($2 AND posts.id IN (
+                SELECT reactions.post_id FROM reactions
+                JOIN posts ON posts.id = reactions.post_id
+                WHERE reactions.reaction_type = TRUE AND posts.created_at > NOW() - INTERVAL '7 days'
+                GROUP BY reactions.post_id
+                ORDER BY COUNT(*) DESC
+                LIMIT 50
+            )))
+            AND can_view_posts_of(posts.user_id, $1)
+            AND NOT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $1 AND muted_id = posts.user_id)
+        ORDER BY posts.created_at DESC
+        LIMIT $3 OFFSET $4",
+        user.id,
+        mix_popular,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "posts" : posts,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
+
+/// Pinned posts come first, the most recently pinned on top.
+pub async fn get_user_posts(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    AppPath(username): AppPath<String>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let user_id = find_user_id(&data, &username).await?;
+    let viewer = current_user_id(&session, &data).await?;
+    if is_blocked(&data, viewer, user_id).await? {
+        return Err(AppError::JsendFail(json!({"username" : "user does not exist"})));
+    }
+    if !can_view_posts(&data, viewer, user_id).await? {
+        return Err(AppError::JsendFail(json!({"username" : "this account is private"})));
+    }
+
+    let posts: Vec<SqlJson<PostResponse>> = sqlx::query_scalar!(
+        "SELECT post_response(posts.id, $4) AS \"post!: SqlJson<PostResponse>\"
+        FROM posts
+        WHERE posts.user_id = $1 AND posts.status = 'published'
+        ORDER BY posts.pinned_at DESC NULLS LAST, posts.created_at DESC
+        LIMIT $2 OFFSET $3",
+        user_id,
+        pagination.per_page,
+        pagination.offset(),
+        viewer
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "posts" : posts,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
+
 pub async fn create_post(
     Extension(user): Extension<UserModel>,
     State(data): State<Arc<AppState>>,
     AppJson(post): AppJson<PostSchema>,
 ) -> Result<impl IntoResponse, AppError> {
     post.validate()?;
-    sqlx::query!(
-        "INSERT INTO posts (user_id,title,content) VALUES ($1,$2,$3)",
-        user.id,
+    let user_id = user.id.ok_or(AppError::InternalServerError)?;
+    let quoted = check_publishable(&data, user_id, post.community_id, post.quote_of_id).await?;
+    if post.draft && post.publish_at.is_some() {
+        return Err(AppError::JsendFail(
+            json!({"publish_at" : "drafts cannot be scheduled"}),
+        ));
+    }
+    // a publish time that already passed publishes right away
+    let publish_at = post.publish_at.filter(|publish_at| *publish_at > Utc::now());
+    let status = if post.draft {
+        PostStatus::Draft
+    } else if publish_at.is_some() {
+        PostStatus::Scheduled
+    } else {
+        PostStatus::Published
+    };
+    let tags = collect_tags(&post.content, &post.tags);
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let post_id = sqlx::query_scalar!(
+        "INSERT INTO posts (user_id,title,content,community_id,quote_of_id,status,publish_at) VALUES ($1,$2,$3,$4,$5,$6,$7) RETURNING id",
+        user_id,
         post.title,
-        post.content
+        post.content,
+        post.community_id,
+        quoted.map(|(quote_of_id, _)| quote_of_id),
+        status.as_str(),
+        publish_at
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    save_post_tags(&mut tx, post_id, &tags).await?;
+    // drafts and scheduled posts notify nobody until they are published
+    let notified = if status == PostStatus::Published {
+        let quoted_author = quoted.map(|(_, quoted_author)| quoted_author);
+        Some(record_publication(&data, &mut tx, post_id, user_id, &post.content, quoted_author).await?)
+    } else {
+        None
+    };
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    if let Some(notified) = notified {
+        announce(&data, post_id, user_id, notified).await;
+    }
+
+    let response = JsendResponse::success(Some(json!({
+        "post_id" : post_id,
+        "tags" : tags,
+        "status" : status.as_str(),
+        "publish_at" : publish_at,
+    })));
+
+    Ok(Json(response))
+}
+
+/// The checks a post has to pass whenever it is published: the author is a
+/// member of its community and the quoted post may be shared. Returns the
+/// quoted post and its author.
+pub async fn check_publishable(
+    data: &AppState,
+    user_id: Uuid,
+    community_id: Option<Uuid>,
+    quote_of_id: Option<Uuid>,
+) -> Result<Option<(Uuid, Uuid)>, AppError> {
+    if let Some(community_id) = community_id {
+        let role = community_role(data, community_id, Some(user_id)).await?;
+        if !role.is_some_and(CommunityRole::is_member) {
+            return Err(AppError::JsendFail(
+                json!({"community" : "you must be a member of this community to post in it"}),
+            ));
+        }
+    }
+    match quote_of_id {
+        Some(quote_of_id) => Ok(Some(shareable_post(data, user_id, quote_of_id).await?)),
+        None => Ok(None),
+    }
+}
+
+/// Resolves the post a repost or quote points at, following plain reposts to
+/// their original. Posts of private accounts can only be shared by their
+/// author, so they never reach people the author has not approved.
+async fn shareable_post(
+    data: &AppState,
+    user_id: Uuid,
+    post_id: Uuid,
+) -> Result<(Uuid, Uuid), AppError> {
+    let post = sqlx::query!(
+        "SELECT posts.id, posts.user_id, profiles.is_private
+        FROM posts
+        JOIN profiles ON profiles.user_id = posts.user_id
+        WHERE posts.id = (SELECT COALESCE(repost_of_id, id) FROM posts WHERE id = $1)
+            AND posts.status = 'published'",
+        post_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or(AppError::JsendFail(json!({"post" : "post doesnt exist"})))?;
+
+    if is_blocked(data, Some(user_id), post.user_id).await? {
+        return Err(AppError::JsendFail(json!({"post" : "post doesnt exist"})));
+    }
+    if post.is_private && post.user_id != user_id {
+        return Err(AppError::JsendFail(
+            json!({"post" : "posts from private accounts cannot be shared"}),
+        ));
+    }
+    Ok((post.id, post.user_id))
+}
+
+/// A repost is an empty post of the reposting user that embeds the original.
+pub async fn repost_post(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    AppPath(postid): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let post_id = Uuid::parse_str(&postid)
+        .map_err(|_| AppError::JsendFail(json!({"post_id" : "not a valid UUID"})))?;
+    let user_id = user.id.ok_or(AppError::InternalServerError)?;
+    let (original_id, author_id) = shareable_post(&data, user_id, post_id).await?;
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let repost_id = sqlx::query_scalar!(
+        "INSERT INTO posts (user_id, title, content, repost_of_id) VALUES ($1, '', '', $2)
+        ON CONFLICT (user_id, repost_of_id) WHERE repost_of_id IS NOT NULL DO NOTHING
+        RETURNING id",
+        user_id,
+        original_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or(AppError::JsendFail(
+        json!({"repost" : "you already reposted this post"}),
+    ))?;
+    let kind = NotificationKind::Repost;
+    let mut notified = Vec::new();
+    if notify(&mut *tx, author_id, user_id, kind, Some(original_id), None).await? {
+        notified.push((author_id, kind));
+    }
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    announce(&data, repost_id, user_id, notified).await;
+
+    let response = JsendResponse::success(Some(json!({
+        "post_id" : repost_id,
+        "repost_of_id" : original_id,
+    })));
+    Ok(Json(response))
+}
+
+pub async fn undo_repost(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    AppPath(postid): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let post_id = Uuid::parse_str(&postid)
+        .map_err(|_| AppError::JsendFail(json!({"post_id" : "not a valid UUID"})))?;
+
+    let deleted = sqlx::query!(
+        "DELETE FROM posts WHERE user_id = $1 AND repost_of_id = $2",
+        user.id,
+        post_id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    if deleted.rows_affected() == 0 {
+        return Err(AppError::JsendFail(
+            json!({"repost" : "you have not reposted this post"}),
+        ));
+    }
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+/// Pins the post unless the user already has `max_pinned` pinned posts.
+async fn pin(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    post_id: Uuid,
+    max_pinned: i64,
+) -> Result<(), AppError> {
+    // locking the user row serializes the user's pins, otherwise concurrent pins
+    // could all count the same pinned posts and exceed the limit together
+    sqlx::query!("SELECT id FROM users WHERE id = $1 FOR UPDATE", user_id)
+        .fetch_one(&mut **tx)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let pinned_count = sqlx::query_scalar!(
+        "SELECT COUNT(*) AS \"count!\" FROM posts WHERE user_id = $1 AND pinned_at IS NOT NULL",
+        user_id
+    )
+    .fetch_one(&mut **tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    if pinned_count >= max_pinned {
+        return Err(AppError::JsendFail(json!({
+            "pin" : format!("you can pin at most {} posts", max_pinned)
+        })));
+    }
+
+    let pinned = sqlx::query!(
+        "UPDATE posts SET pinned_at = NOW() WHERE id = $1 AND pinned_at IS NULL",
+        post_id
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    // a concurrent request pinned it first
+    if pinned.rows_affected() == 0 {
+        return Err(AppError::JsendFail(
+            json!({"pin" : "post is already pinned"}),
+        ));
+    }
+    Ok(())
+}
+
+/// Pins one of the user's own posts to the top of their profile. Plain reposts
+/// cannot be pinned, and pinning a pinned post fails just like unpinning a post
+/// that is not pinned.
+pub async fn pin_post(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    AppPath(postid): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let post_id = Uuid::parse_str(&postid)
+        .map_err(|_| AppError::JsendFail(json!({"post_id" : "not a valid UUID"})))?;
+
+    let post = sqlx::query!(
+        "SELECT user_id, repost_of_id, pinned_at FROM posts WHERE id = $1 AND status = 'published'",
+        post_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or(AppError::JsendFail(json!({"post" : "post doesnt exist"})))?;
+
+    if user.id != Some(post.user_id) || post.repost_of_id.is_some() {
+        return Err(AppError::JsendFail(
+            json!({"authorization" : "you can only pin your own posts"}),
+        ));
+    }
+    if post.pinned_at.is_some() {
+        return Err(AppError::JsendFail(
+            json!({"pin" : "post is already pinned"}),
+        ));
+    }
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    pin(&mut tx, post.user_id, post_id, data.env.max_pinned_posts).await?;
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+/// Unpins one of the user's posts, failing when it is not pinned.
+pub async fn unpin_post(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    AppPath(postid): AppPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let post_id = Uuid::parse_str(&postid)
+        .map_err(|_| AppError::JsendFail(json!({"post_id" : "not a valid UUID"})))?;
+
+    let unpinned = sqlx::query!(
+        "UPDATE posts SET pinned_at = NULL WHERE id = $1 AND user_id = $2 AND pinned_at IS NOT NULL",
+        post_id,
+        user.id
     )
     .execute(&data.db)
     .await
     .map_err(|_| AppError::InternalServerError)?;
 
+    if unpinned.rows_affected() == 0 {
+        return Err(AppError::JsendFail(json!({"post" : "post doesnt exist"})));
+    }
+
     let response = JsendResponse::success(None);
+    Ok(Json(response))
+}
+
+/// Publishes one of the user's drafts or scheduled posts, or schedules it when
+/// `publish_at` lies in the future.
+pub async fn publish_post_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    AppPath(postid): AppPath<String>,
+    AppJson(publish): AppJson<PublishSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    let post_id = Uuid::parse_str(&postid)
+        .map_err(|_| AppError::JsendFail(json!({"post_id" : "not a valid UUID"})))?;
+    let user_id = user.id.ok_or(AppError::InternalServerError)?;
 
+    let status = sqlx::query_scalar!(
+        "SELECT status FROM posts WHERE id = $1 AND user_id = $2",
+        post_id,
+        user_id
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or(AppError::JsendFail(json!({"post" : "post doesnt exist"})))?;
+    if status == PostStatus::Published.as_str() {
+        return Err(AppError::JsendFail(
+            json!({"post" : "post is already published"}),
+        ));
+    }
+
+    if let Some(publish_at) = publish.publish_at.filter(|publish_at| *publish_at > Utc::now()) {
+        sqlx::query!(
+            "UPDATE posts SET status = $1, publish_at = $2, publish_attempts = 0, updated_at = NOW()
+            WHERE id = $3 AND status <> 'published'",
+            PostStatus::Scheduled.as_str(),
+            publish_at,
+            post_id
+        )
+        .execute(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+        let response = JsendResponse::success(Some(json!({
+            "post_id" : post_id,
+            "status" : PostStatus::Scheduled.as_str(),
+            "publish_at" : publish_at,
+        })));
+        return Ok(Json(response));
+    }
+
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    let notified = publish_post(&data, &mut tx, post_id).await?;
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    announce(&data, post_id, user_id, notified).await;
+
+    let response = JsendResponse::success(Some(json!({
+        "post_id" : post_id,
+        "status" : PostStatus::Published.as_str(),
+    })));
+    Ok(Json(response))
+}
+
+/// The user's unpublished posts, the next scheduled ones first and then drafts
+/// by when they were last changed.
+pub async fn get_drafts(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let posts: Vec<SqlJson<PostResponse>> = sqlx::query_scalar!(
+        "SELECT post_response(posts.id, $1) AS \"post!: SqlJson<PostResponse>\"
+        FROM posts
+        WHERE posts.user_id = $1 AND posts.status <> 'published'
+        ORDER BY posts.publish_at NULLS LAST, posts.updated_at DESC
+        LIMIT $2 OFFSET $3",
+        user.id,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "posts" : posts,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
     Ok(Json(response))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::auth_handlers::create_user_with_profile;
+    use sqlx::PgPool;
+
+    async fn create_user(db: &PgPool, username: &str) -> Uuid {
+        let mut tx = db.begin().await.unwrap();
+        let email = format!("{}@example.com", username);
+        let user_id = create_user_with_profile(&mut tx, username, &email, None)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+        user_id
+    }
+
+    async fn post_response(db: &PgPool, post_id: Uuid, viewer: Option<Uuid>) -> PostResponse {
+        sqlx::query_scalar!(
+            "SELECT post_response($1, $2) AS \"post!: SqlJson<PostResponse>\"",
+            post_id,
+            viewer
+        )
+        .fetch_one(db)
+        .await
+        .unwrap()
+        .0
+    }
+
+    #[sqlx::test]
+    async fn post_response_builds_the_whole_response(db: PgPool) {
+        let author = create_user(&db, "author").await;
+        let reader = create_user(&db, "reader").await;
+        let post_id = sqlx::query_scalar!(
+            "INSERT INTO posts (user_id, title, content) VALUES ($1, 'title', 'hi @reader') RETURNING id",
+            author
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        sqlx::query!(
+            "INSERT INTO mentions (post_id, user_id, author_id) VALUES ($1, $2, $3)",
+            post_id,
+            reader,
+            author
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        sqlx::query!(
+            "INSERT INTO reactions (user_id, post_id, reaction_type) VALUES ($1, $2, TRUE)",
+            reader,
+            post_id
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        let repost_id = sqlx::query_scalar!(
+            "INSERT INTO posts (user_id, title, content, repost_of_id) VALUES ($1, '', '', $2) RETURNING id",
+            reader,
+            post_id
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+
+        let post = post_response(&db, post_id, Some(reader)).await;
+        assert_eq!(post.username, "author");
+        assert_eq!(post.likes, Some(1));
+        assert_eq!(post.dislikes, Some(0));
+        assert_eq!(post.repost_count, 1);
+        assert_eq!(post.is_bookmarked, Some(false));
+        assert_eq!(post.content_html.mentions, ["reader"]);
+
+        let repost = post_response(&db, repost_id, Some(reader)).await;
+        assert_eq!(repost.original.map(|original| original.id), Some(post_id));
+
+        // anonymous callers get no bookmark flag at all
+        let anonymous = serde_json::to_value(post_response(&db, post_id, None).await).unwrap();
+        assert!(anonymous.get("is_bookmarked").is_none());
+    }
+
+    async fn create_post(db: &PgPool, user_id: Uuid) -> Uuid {
+        sqlx::query_scalar!(
+            "INSERT INTO posts (user_id, title, content) VALUES ($1, 'title', 'content') RETURNING id",
+            user_id
+        )
+        .fetch_one(db)
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn pinning_a_pinned_post_fails(db: PgPool) {
+        let author = create_user(&db, "author").await;
+        let post_id = create_post(&db, author).await;
+
+        let mut tx = db.begin().await.unwrap();
+        pin(&mut tx, author, post_id, 3).await.unwrap();
+        let again = pin(&mut tx, author, post_id, 3).await;
+        assert!(matches!(again, Err(AppError::JsendFail(_))));
+    }
+
+    #[sqlx::test]
+    async fn concurrent_pins_respect_the_limit(db: PgPool) {
+        let author = create_user(&db, "author").await;
+        let first_post = create_post(&db, author).await;
+        let second_post = create_post(&db, author).await;
+
+        let mut first = db.begin().await.unwrap();
+        pin(&mut first, author, first_post, 1).await.unwrap();
+
+        let second = std::thread::spawn({
+            let db = db.clone();
+            move || {
+                tokio::runtime::Runtime::new()
+                    .unwrap()
+                    .block_on(async move {
+                        let mut tx = db.begin().await.unwrap();
+                        let pinned = pin(&mut tx, author, second_post, 1).await;
+                        tx.commit().await.unwrap();
+                        pinned.is_ok()
+                    })
+            }
+        });
+
+        // the second pin waits for the first instead of counting the same pinned posts
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        assert!(!second.is_finished());
+        first.commit().await.unwrap();
+        assert!(!second.join().unwrap());
+    }
+}
@@ -1,6 +1,8 @@
 use crate::{
     model::{ProfileModel, UserModel},
-    response::{AppError, AppPath, JsendResponse},
+    realtime::RealtimeEvent,
+    response::{AppError, AppJson, AppPath, JsendResponse},
+    schema::UpdateProfileSchema,
     AppState,
 };
 use axum::{
@@ -13,6 +15,7 @@ use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
 use std::sync::Arc;
+use validator::Validate;
 
 pub async fn get_profile(
     AppPath(username): AppPath<String>,
@@ -36,7 +39,7 @@ pub async fn get_profile(
     // Query to find the profile by user_id
     let profile: Option<ProfileModel> = sqlx::query_as!(
         ProfileModel,
-        "SELECT id, user_id, profile_image, bio, created_at, updated_at FROM profiles WHERE user_id = $1",
+        "SELECT id, user_id, profile_image, bio, display_name, website, location, pronouns, is_private, created_at, updated_at FROM profiles WHERE user_id = $1",
         user_id
     )
     .fetch_optional(&data.db)
@@ -53,6 +56,103 @@ pub async fn get_profile(
         }
     };
 
+    let counts = sqlx::query!(
+        "SELECT
+            (SELECT COUNT(*) FROM follows WHERE followee_id = $1) AS followers,
+            (SELECT COUNT(*) FROM follows WHERE follower_id = $1) AS following,
+            (SELECT COUNT(*) FROM posts WHERE user_id = $1 AND status = 'published') AS posts",
+        user_id
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "profile" : profile,
+        "follower_count" : counts.followers,
+        "following_count" : counts.following,
+        "post_count" : counts.posts,
+    })));
+    Ok(Json(response))
+}
+
+pub async fn update_profile(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    AppJson(mut body): AppJson<UpdateProfileSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    // validate the values as they will be stored
+    for field in [
+        &mut body.bio,
+        &mut body.display_name,
+        &mut body.website,
+        &mut body.location,
+        &mut body.pronouns,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        *field = field.trim().to_string();
+    }
+    body.validate()?;
+    let mut tx = data
+        .db
+        .begin()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    let profile = sqlx::query_as!(
+        ProfileModel,
+        "UPDATE profiles SET
+            bio = COALESCE($1, bio),
+            display_name = COALESCE($2, display_name),
+            website = COALESCE($3, website),
+            location = COALESCE($4, location),
+            pronouns = COALESCE($5, pronouns),
+            is_private = COALESCE($6, is_private),
+            updated_at = NOW()
+        WHERE user_id = $7
+        RETURNING id, user_id, profile_image, bio, display_name, website, location, pronouns, is_private, created_at, updated_at",
+        body.bio.as_deref(),
+        body.display_name.as_deref(),
+        body.website.as_deref(),
+        body.location.as_deref(),
+        body.pronouns.as_deref(),
+        body.is_private,
+        user.id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?
+    .ok_or_else(|| AppError::JsendFail(json!({"profile" : "profile not found"})))?;
+
+    // going public accepts everyone who was still waiting for approval
+    if !profile.is_private {
+        sqlx::query!(
+            "WITH approved AS (DELETE FROM follow_requests WHERE target_id = $1 RETURNING requester_id)
+            INSERT INTO follows (follower_id, followee_id)
+            SELECT requester_id, $1 FROM approved
+            ON CONFLICT DO NOTHING",
+            user.id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    // going private hides the posts from everyone but the followers
+    if body.is_private == Some(true) {
+        data.realtime
+            .publish(RealtimeEvent::VisibilityChanged {
+                user_ids: vec![profile.user_id],
+            })
+            .await;
+    }
+
     let response = JsendResponse::success(Some(json!({"profile" : profile})));
     Ok(Json(response))
 }
@@ -0,0 +1,210 @@
+use crate::{
+    handlers::{
+        block_handlers::{is_blocked, is_muted},
+        follow_handlers::can_view_posts,
+    },
+    model::UserModel,
+    realtime::{Envelope, RealtimeEvent},
+    response::AppError,
+    session_auth::session_ended,
+    AppState,
+};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::IntoResponse,
+    Extension,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+const MAX_SUBSCRIPTIONS: usize = 100;
+
+/// Messages clients send to follow the comments and reactions of a post.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe { post_id: Uuid },
+    Unsubscribe { post_id: Uuid },
+}
+
+pub async fn realtime_handler(
+    ws: WebSocketUpgrade,
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<UserModel>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id = user.id.ok_or(AppError::InternalServerError)?;
+    let session_epoch = user.session_epoch;
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, data, user_id, session_epoch)))
+}
+
+/// Pushes every new post the user may see, comment changes and reaction counts
+/// of the subscribed posts, the user's own notifications and the messages of
+/// their conversations. Subscriptions are kept with the post author so they can
+/// be dropped when a block or privacy change hides the post. The connection
+/// closes when the session it was opened with ends.
+async fn handle_socket(
+    mut socket: WebSocket,
+    data: Arc<AppState>,
+    user_id: Uuid,
+    session_epoch: i32,
+) {
+    let mut events = data.realtime.subscribe();
+    if session_ended(&data, user_id, session_epoch)
+        .await
+        .unwrap_or(true)
+    {
+        let _ = socket.send(Message::Close(None)).await;
+        return;
+    }
+    let mut posts: HashMap<Uuid, Uuid> = HashMap::new();
+
+    loop {
+        tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let reply = match handle_message(&data, user_id, &mut posts, &text).await {
+                        Ok(reply) => reply,
+                        Err(_) => json!({"type" : "error", "message" : "internal server error"}),
+                    };
+                    if socket.send(Message::Text(reply.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            event = events.recv() => match event {
+                Ok(Envelope {
+                    event: RealtimeEvent::VisibilityChanged { user_ids },
+                    ..
+                }) => {
+                    let hidden = recheck_subscriptions(&data, user_id, &mut posts, &user_ids).await;
+                    for post_id in hidden.unwrap_or_default() {
+                        let reply = json!({"type" : "unsubscribed", "post_id" : post_id});
+                        if socket.send(Message::Text(reply.to_string())).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                Ok(Envelope { event, .. }) if event.ends_session(user_id, session_epoch) => {
+                    let _ = socket.send(Message::Close(None)).await;
+                    break;
+                }
+                Ok(Envelope { event, audience }) => {
+                    if !audience.includes(user_id) {
+                        continue;
+                    }
+                    let deliver = should_deliver(&data, user_id, &posts, &event).await;
+                    if deliver.unwrap_or(false) {
+                        let Ok(payload) = serde_json::to_string(&event) else {
+                            continue;
+                        };
+                        if socket.send(Message::Text(payload)).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("realtime connection of {} skipped {} events", user_id, skipped);
+                }
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+}
+
+async fn handle_message(
+    data: &AppState,
+    user_id: Uuid,
+    posts: &mut HashMap<Uuid, Uuid>,
+    text: &str,
+) -> Result<serde_json::Value, AppError> {
+    let Ok(message) = serde_json::from_str::<ClientMessage>(text) else {
+        return Ok(json!({"type" : "error", "message" : "invalid message"}));
+    };
+
+    match message {
+        ClientMessage::Subscribe { post_id } => {
+            if posts.len() >= MAX_SUBSCRIPTIONS {
+                return Ok(json!({"type" : "error", "message" : "too many subscriptions"}));
+            }
+            let author_id = sqlx::query_scalar!(
+                "SELECT user_id FROM posts WHERE id = $1 AND status = 'published'",
+                post_id
+            )
+            .fetch_optional(&data.db)
+            .await
+            .map_err(|_| AppError::InternalServerError)?;
+            let author_id = match author_id {
+                Some(author_id) if can_see(data, user_id, author_id).await? => author_id,
+                _ => return Ok(json!({"type" : "error", "message" : "post doesnt exist"})),
+            };
+            posts.insert(post_id, author_id);
+            Ok(json!({"type" : "subscribed", "post_id" : post_id}))
+        }
+        ClientMessage::Unsubscribe { post_id } => {
+            posts.remove(&post_id);
+            Ok(json!({"type" : "unsubscribed", "post_id" : post_id}))
+        }
+    }
+}
+
+async fn can_see(data: &AppState, user_id: Uuid, author_id: Uuid) -> Result<bool, AppError> {
+    Ok(!is_blocked(data, Some(user_id), author_id).await?
+        && can_view_posts(data, Some(user_id), author_id).await?)
+}
+
+/// Drops the subscriptions to posts by the given users that the user may no
+/// longer see and returns them.
+async fn recheck_subscriptions(
+    data: &AppState,
+    user_id: Uuid,
+    posts: &mut HashMap<Uuid, Uuid>,
+    changed: &[Uuid],
+) -> Result<Vec<Uuid>, AppError> {
+    let mut hidden = Vec::new();
+    for (&post_id, &author_id) in posts.iter() {
+        if changed.contains(&author_id) && !can_see(data, user_id, author_id).await? {
+            hidden.push(post_id);
+        }
+    }
+    for post_id in &hidden {
+        posts.remove(post_id);
+    }
+    Ok(hidden)
+}
+
+/// Applies the same visibility rules as the REST endpoints to a pushed event.
+/// New posts and conversation events were already narrowed down to their
+/// audience by the publisher.
+async fn should_deliver(
+    data: &AppState,
+    user_id: Uuid,
+    posts: &HashMap<Uuid, Uuid>,
+    event: &RealtimeEvent,
+) -> Result<bool, AppError> {
+    match event {
+        RealtimeEvent::NewPost { .. } => Ok(true),
+        RealtimeEvent::Comment {
+            post_id,
+            user_id: author_id,
+            ..
+        } => Ok(posts.contains_key(post_id)
+            && !is_blocked(data, Some(user_id), *author_id).await?
+            && !is_muted(data, user_id, *author_id).await?),
+        RealtimeEvent::Reactions { post_id, .. } => Ok(posts.contains_key(post_id)),
+        RealtimeEvent::VisibilityChanged { .. } | RealtimeEvent::SessionsEnded { .. } => {
+            Ok(false)
+        }
+        RealtimeEvent::Notification {
+            user_id: recipient, ..
+        } => Ok(*recipient == user_id),
+        RealtimeEvent::Message { .. } | RealtimeEvent::MessagesRead { .. } => Ok(true),
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{
+    handlers::user_handlers::escape_like,
+    model::{CommentSearchResult, PostSearchResult, ProfileResponse},
+    response::{AppError, AppQuery, JsendResponse},
+    schema::{PaginationSchema, SearchSchema},
+    session_auth::current_user_id,
+    AppState,
+};
+use axum::{extract::State, response::IntoResponse, Json};
+use serde_json::json;
+use std::sync::Arc;
+use tower_sessions::Session;
+use validator::Validate;
+
+/// Searches posts, comments and users at once. Posts and comments are matched
+/// with the full-text index and ranked by relevance, matches are wrapped in
+/// <b></b> tags and the rest of the highlight is HTML escaped. Each category is
+/// paginated on its own.
+pub async fn search_handler(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    AppQuery(search): AppQuery<SearchSchema>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    search.validate()?;
+    pagination.validate()?;
+    let viewer = current_user_id(&session, &data).await?;
+    let q = search.q.trim();
+    if q.is_empty() {
+        return Err(AppError::JsendFail(
+            json!({"q" : "search must be between 1 and 100 characters"}),
+        ));
+    }
+
+    let posts = sqlx::query_as!(
+        PostSearchResult,
+        r#"SELECT
+            posts.id,
+            posts.user_id,
+            users.username,
+            profiles.display_name,
+            profiles.profile_image,
+            posts.title,
+            posts.content,
+            ts_headline('english', html_escape(posts.title), query, 'HighlightAll=true') AS "title_highlight!",
+            ts_headline('english', html_escape(posts.content), query, 'MaxFragments=2') AS "content_highlight!",
+            ts_rank(posts.search_vector, query) AS "rank!",
+            posts.created_at
+        FROM posts
+        CROSS JOIN websearch_to_tsquery('english', $1) AS query
+        JOIN users ON posts.user_id = users.id
+        JOIN profiles ON profiles.user_id = users.id
+        WHERE posts.search_vector @@ query
+            AND posts.status = 'published'
+            AND users.suspended_at IS NULL
+            AND NOT EXISTS (
+                SELECT 1 FROM blocks
+                WHERE (blocker_id = $2 AND blocked_id = posts.user_id)
+                    OR (blocker_id = posts.user_id AND blocked_id = $2)
+            )
+            AND NOT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $2 AND muted_id = posts.user_id)
+            AND (NOT profiles.is_private
+                OR posts.user_id = $2
+                OR EXISTS (SELECT 1 FROM follows WHERE follower_id = $2 AND followee_id = posts.user_id))
+        ORDER BY "rank!" DESC, posts.created_at DESC
+        LIMIT $3 OFFSET $4"#,
+        q,
+        viewer,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let comments = sqlx::query_as!(
+        CommentSearchResult,
+        r#"SELECT
+            comments.id,
+            comments.user_id,
+            comments.post_id,
+            users.username,
+            profiles.display_name,
+            profiles.profile_image,
+            comments.content,
+            ts_headline('english', html_escape(comments.content), query, 'MaxFragments=2') AS "content_highlight!",
+            ts_rank(comments.search_vector, query) AS "rank!",
+            comments.created_at
+        FROM comments
+        CROSS JOIN websearch_to_tsquery('english', $1) AS query
+        JOIN users ON comments.user_id = users.id
+        JOIN profiles ON profiles.user_id = users.id
+        JOIN posts ON comments.post_id = posts.id
+        JOIN profiles AS authors ON posts.user_id = authors.user_id
+        WHERE comments.search_vector @@ query
+            AND users.suspended_at IS NULL
+            AND NOT EXISTS (
+                SELECT 1 FROM blocks
+                WHERE (blocker_id = $2 AND blocked_id IN (comments.user_id, posts.user_id))
+                    OR (blocked_id = $2 AND blocker_id IN (comments.user_id, posts.user_id))
+            )
+            AND NOT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $2 AND muted_id = comments.user_id)
+            AND (NOT authors.is_private
+                OR posts.user_id = $2
+                OR EXISTS (SELECT 1 FROM follows WHERE follower_id = $2 AND followee_id = posts.user_id))
+        ORDER BY "rank!" DESC, comments.created_at DESC
+        LIMIT $3 OFFSET $4"#,
+        q,
+        viewer,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let users = sqlx::query_as!(
+        ProfileResponse,
+        "SELECT profiles.id AS profile_id, profiles.profile_image, username, profiles.display_name, profiles.bio, profiles.website, profiles.location, profiles.pronouns
+        FROM users
+        JOIN profiles on users.id = profiles.user_id
+        WHERE users.suspended_at IS NULL
+            AND (users.username ILIKE $1 || '%' OR users.username % $5 OR profiles.display_name ILIKE '%' || $1 || '%')
+            AND NOT EXISTS (
+                SELECT 1 FROM blocks
+                WHERE (blocker_id = $2 AND blocked_id = users.id)
+                    OR (blocker_id = users.id AND blocked_id = $2)
+            )
+        ORDER BY (users.username ILIKE $1 || '%') DESC, similarity(users.username, $5) DESC, users.created_at DESC
+        LIMIT $3 OFFSET $4",
+        escape_like(q),
+        viewer,
+        pagination.per_page,
+        pagination.offset(),
+        q
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "posts" : posts,
+        "comments" : comments,
+        "users" : users,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
@@ -0,0 +1,99 @@
+use crate::{
+    model::{PostResponse, TrendingTagResponse},
+    response::{AppError, AppPath, AppQuery, JsendResponse},
+    schema::PaginationSchema,
+    session_auth::current_user_id,
+    AppState,
+};
+use axum::{extract::State, response::IntoResponse, Json};
+use serde_json::json;
+use sqlx::types::Json as SqlJson;
+use std::sync::Arc;
+use tower_sessions::Session;
+use validator::Validate;
+
+pub async fn get_tag_posts(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    AppPath(tag): AppPath<String>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let viewer = current_user_id(&session, &data).await?;
+    let tag = tag.trim_start_matches('#').to_lowercase();
+
+    let posts: Vec<SqlJson<PostResponse>> = sqlx::query_scalar!(
+        "SELECT post_response(posts.id, $2) AS \"post!: SqlJson<PostResponse>\"
+        FROM posts
+        JOIN post_tags ON post_tags.post_id = posts.id
+        JOIN tags ON tags.id = post_tags.tag_id
+        WHERE tags.name = $1
+            AND posts.status = 'published'
+            AND can_view_posts_of(posts.user_id, $2)
+            AND NOT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $2 AND muted_id = posts.user_id)
+        ORDER BY posts.created_at DESC
+        LIMIT $3 OFFSET $4",
+        tag,
+        viewer,
+        pagination.per_page,
+        pagination.offset()
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "tag" : tag,
+        "posts" : posts,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
+
+/// Tags ranked by how many posts used them within the trending window. Only
+/// posts by public accounts count, so private posts never surface through their
+/// tags, and posts by users the caller blocked, muted or was blocked by are left
+/// out of the caller's counts.
+pub async fn get_trending_tags(
+    session: Session,
+    State(data): State<Arc<AppState>>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
+) -> Result<impl IntoResponse, AppError> {
+    pagination.validate()?;
+    let viewer = current_user_id(&session, &data).await?;
+    let tags = sqlx::query_as!(
+        TrendingTagResponse,
+        r#"SELECT tags.name, COUNT(*) AS "post_count!"
+        FROM post_tags
+        JOIN tags ON tags.id = post_tags.tag_id
+        JOIN posts ON posts.id = post_tags.post_id
+        JOIN profiles ON profiles.user_id = posts.user_id
+        WHERE posts.status = 'published'
+            AND post_tags.created_at > NOW() - make_interval(hours => $1)
+            AND NOT profiles.is_private
+            AND NOT EXISTS (
+                SELECT 1 FROM blocks
+                WHERE (blocker_id = $4 AND blocked_id = posts.user_id)
+                    OR (blocker_id = posts.user_id AND blocked_id = $4)
+            )
+            AND NOT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $4 AND muted_id = posts.user_id)
+        GROUP BY tags.id, tags.name
+        ORDER BY COUNT(*) DESC, MAX(post_tags.created_at) DESC
+        LIMIT $2 OFFSET $3"#,
+        data.env.trending_tags_window_hours,
+        pagination.per_page,
+        pagination.offset(),
+        viewer
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let response = JsendResponse::success(Some(json!({
+        "tags" : tags,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
+    Ok(Json(response))
+}
@@ -1,23 +1,93 @@
 use crate::{
-    model::{ProfileResponse, UserResponse},
-    response::{AppError, JsendResponse},
+    model::ProfileResponse,
+    response::{AppError, AppQuery, JsendResponse},
+    schema::{PaginationSchema, UserSearchSchema, UserSort},
     AppState,
 };
 use axum::{extract::State, response::IntoResponse, Json};
 use serde_json::json;
 use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
 
 pub async fn get_all_users(
     State(data): State<Arc<AppState>>,
+    AppQuery(search): AppQuery<UserSearchSchema>,
+    AppQuery(pagination): AppQuery<PaginationSchema>,
 ) -> Result<impl IntoResponse, AppError> {
+    search.validate()?;
+    pagination.validate()?;
+    let query = search.q.as_deref().map(str::trim).filter(|q| !q.is_empty());
+
+    // prefix matches rank first, then trigram similarity catches typos. Only the
+    // prefix match escapes the LIKE wildcards, similarity compares the raw query
     let users: Vec<ProfileResponse> = sqlx::query_as!(
         ProfileResponse,
-        "SELECT profiles.id AS profile_id, profiles.profile_image, username FROM users JOIN profiles on users.id = profiles.user_id"
+        "SELECT profiles.id AS profile_id, profiles.profile_image, username, profiles.display_name, profiles.bio, profiles.website, profiles.location, profiles.pronouns
+        FROM users
+        JOIN profiles on users.id = profiles.user_id
+        WHERE users.suspended_at IS NULL
+            AND ($1::text IS NULL OR users.username ILIKE $2 || '%' OR users.username % $1)
+        ORDER BY
+            CASE WHEN $1::text IS NULL THEN 0 WHEN users.username ILIKE $2 || '%' THEN 2 ELSE 1 END DESC,
+            CASE WHEN $1::text IS NULL THEN 0 ELSE similarity(users.username, $1) END DESC,
+            CASE WHEN $3 THEN GREATEST(
+                (SELECT MAX(created_at) FROM posts WHERE posts.user_id = users.id AND posts.status = 'published'),
+                (SELECT MAX(created_at) FROM comments WHERE comments.user_id = users.id)
+            ) END DESC NULLS LAST,
+            users.created_at DESC
+        LIMIT $4 OFFSET $5",
+        query,
+        query.map(escape_like),
+        search.sort == UserSort::Activity,
+        pagination.per_page,
+        pagination.offset()
     )
     .fetch_all(&data.db)
     .await
     .map_err(|_| AppError::InternalServerError)?;
 
-    let response = JsendResponse::success(Some(json!({"users" : users})));
+    let response = JsendResponse::success(Some(json!({
+        "users" : users,
+        "page" : pagination.page,
+        "per_page" : pagination.per_page,
+    })));
     Ok(Json(response))
 }
+
+pub async fn find_user_id(data: &AppState, username: &str) -> Result<Uuid, AppError> {
+    sqlx::query_scalar!("SELECT id FROM users WHERE username = $1", username)
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .ok_or_else(|| AppError::JsendFail(json!({"username" : "user does not exist"})))
+}
+
+/// Escapes the LIKE wildcards so user input only ever matches literally.
+pub fn escape_like(query: &str) -> String {
+    query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_like_escapes_the_wildcards() {
+        assert_eq!(escape_like("a_b%c"), "a\\_b\\%c");
+    }
+
+    #[test]
+    fn escape_like_escapes_the_escape_character_first() {
+        // a backslash in the input must not escape the wildcard after it
+        assert_eq!(escape_like("a\\%"), "a\\\\\\%");
+    }
+
+    #[test]
+    fn escape_like_keeps_plain_input() {
+        assert_eq!(escape_like("someone"), "someone");
+    }
+}
@@ -0,0 +1,386 @@
+use crate::{
+    model::{CommentModel, PostModel, ProfileModel, ReactionModel, UserResponse},
+    publishing::{announce, publish_post, PostStatus},
+    response::AppError,
+    AppState,
+};
+use serde_json::json;
+use sqlx::PgPool;
+use std::{io::Write, path::PathBuf, sync::Arc, time::Duration};
+use uuid::Uuid;
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+/// Where finished data exports are kept until their link expires.
+pub const EXPORTS_DIR: &str = "./exports";
+
+/// Starts the periodic background jobs that run inside the server process.
+pub fn spawn(data: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            if let Err(err) = delete_due_accounts(&data).await {
+                tracing::error!("account deletion job failed: {}", err);
+            }
+            if let Err(err) = process_data_exports(&data).await {
+                tracing::error!("data export job failed: {}", err);
+            }
+            if let Err(err) = remove_expired_exports(&data).await {
+                tracing::error!("export cleanup job failed: {}", err);
+            }
+            if let Err(err) = remove_old_comment_events(&data).await {
+                tracing::error!("comment event cleanup job failed: {}", err);
+            }
+            if let Err(err) = publish_scheduled_posts(&data).await {
+                tracing::error!("scheduled post job failed: {}", err);
+            }
+        }
+    });
+}
+
+/// Deletes accounts whose grace period has passed. Posts, comments, reactions
+/// and the profile go with the user row through ON DELETE CASCADE.
+async fn delete_due_accounts(data: &AppState) -> Result<(), sqlx::Error> {
+    let due =
+        sqlx::query_scalar!("SELECT user_id FROM account_deletions WHERE scheduled_for <= NOW()")
+            .fetch_all(&data.db)
+            .await?;
+
+    for user_id in due {
+        let export_files = sqlx::query_scalar!(
+            "SELECT file_name FROM data_exports WHERE user_id = $1 AND file_name IS NOT NULL",
+            user_id
+        )
+        .fetch_all(&data.db)
+        .await?;
+
+        // logging in cancels the deletion, which may have happened since the
+        // due accounts were listed
+        let deleted = sqlx::query!(
+            "DELETE FROM users WHERE id = $1
+            AND EXISTS (SELECT 1 FROM account_deletions WHERE user_id = $1 AND scheduled_for <= NOW())",
+            user_id
+        )
+        .execute(&data.db)
+        .await?
+        .rows_affected();
+        if deleted == 0 {
+            continue;
+        }
+
+        for file_name in export_files.into_iter().flatten() {
+            remove_file(PathBuf::from(EXPORTS_DIR).join(file_name));
+        }
+        for extension in ["png", "jpg"] {
+            // uploaded avatars are named after the user id, see upload_profile_pic
+            remove_file(PathBuf::from("./assets").join(format!("{}.{}", user_id, extension)));
+        }
+        tracing::info!("deleted account {}", user_id);
+    }
+    Ok(())
+}
+
+fn remove_file(path: PathBuf) {
+    if let Err(err) = std::fs::remove_file(&path) {
+        if err.kind() != std::io::ErrorKind::NotFound {
+            tracing::warn!("failed to remove {:?}: {}", path, err);
+        }
+    }
+}
+
+/// Exports still processing after this long were abandoned by a crashed or
+/// restarted server and are claimed again.
+const EXPORT_CLAIM_TIMEOUT_MINUTES: i32 = 30;
+
+async fn process_data_exports(data: &AppState) -> Result<(), sqlx::Error> {
+    // claim pending exports so several server instances never build the same one
+    let claimed = sqlx::query!(
+        "UPDATE data_exports SET status = 'processing', claimed_at = NOW()
+        WHERE id IN (
+            SELECT id FROM data_exports
+            WHERE status = 'pending'
+                OR (status = 'processing'
+                    AND (claimed_at IS NULL OR claimed_at <= NOW() - make_interval(mins => $1)))
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, user_id",
+        EXPORT_CLAIM_TIMEOUT_MINUTES
+    )
+    .fetch_all(&data.db)
+    .await?;
+
+    for export in claimed {
+        match build_export(data, export.id, export.user_id).await {
+            Ok(file_name) => {
+                sqlx::query!(
+                    "UPDATE data_exports
+                    SET status = 'ready', file_name = $1, completed_at = NOW(),
+                        expires_at = NOW() + make_interval(hours => $2)
+                    WHERE id = $3",
+                    file_name,
+                    data.env.data_export_ttl_hours,
+                    export.id
+                )
+                .execute(&data.db)
+                .await?;
+            }
+            Err(err) => {
+                tracing::error!("failed to build export {}: {}", export.id, err);
+                sqlx::query!(
+                    "UPDATE data_exports SET status = 'failed', completed_at = NOW() WHERE id = $1",
+                    export.id
+                )
+                .execute(&data.db)
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes a zip with the account data as JSON plus the uploaded avatar.
+async fn build_export(
+    data: &AppState,
+    export_id: Uuid,
+    user_id: Uuid,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let user = sqlx::query_as!(
+        UserResponse,
+        "SELECT id, username, email, role, created_at, updated_at, suspended_at FROM users WHERE id = $1",
+        user_id
+    )
+    .fetch_one(&data.db)
+    .await?;
+    let profile = sqlx::query_as!(
+        ProfileModel,
+        "SELECT id, user_id, profile_image, bio, display_name, website, location, pronouns, is_private, created_at, updated_at FROM profiles WHERE user_id = $1",
+        user_id
+    )
+    .fetch_optional(&data.db)
+    .await?;
+    let posts = sqlx::query_as!(
+        PostModel,
+        "SELECT id, user_id, title, content, created_at, updated_at, community_id,
+            repost_of_id, quote_of_id, pinned_at, status, publish_at
+        FROM posts
+        WHERE user_id = $1 ORDER BY created_at",
+        user_id
+    )
+    .fetch_all(&data.db)
+    .await?;
+    let comments = sqlx::query_as!(
+        CommentModel,
+        "SELECT id, user_id, post_id, content, created_at, updated_at FROM comments
+        WHERE user_id = $1 ORDER BY created_at",
+        user_id
+    )
+    .fetch_all(&data.db)
+    .await?;
+    let reactions = sqlx::query_as!(
+        ReactionModel,
+        "SELECT id, user_id, post_id, reaction_type, created_at, updated_at FROM reactions
+        WHERE user_id = $1 ORDER BY created_at",
+        user_id
+    )
+    .fetch_all(&data.db)
+    .await?;
+    let messages: Vec<serde_json::Value> = sqlx::query!(
+        "SELECT id, conversation_id, content, created_at FROM messages
+        WHERE sender_id = $1 ORDER BY seq",
+        user_id
+    )
+    .fetch_all(&data.db)
+    .await?
+    .into_iter()
+    .map(|message| {
+        json!({
+            "id" : message.id,
+            "conversation_id" : message.conversation_id,
+            "content" : message.content,
+            "created_at" : message.created_at,
+        })
+    })
+    .collect();
+
+    let document = serde_json::to_vec_pretty(&json!({
+        "user" : user,
+        "profile" : profile,
+        "posts" : posts,
+        "comments" : comments,
+        "reactions" : reactions,
+        "messages" : messages,
+    }))?;
+    let avatar = profile
+        .map(|profile| profile.profile_image)
+        .filter(|image| image.starts_with(&user_id.to_string()));
+
+    let file_name = format!("{}.zip", export_id);
+    let path = PathBuf::from(EXPORTS_DIR).join(&file_name);
+    tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+        std::fs::create_dir_all(EXPORTS_DIR)?;
+        let mut zip = ZipWriter::new(std::fs::File::create(path)?);
+        let options = SimpleFileOptions::default();
+        zip.start_file("data.json", options)?;
+        zip.write_all(&document)?;
+        if let Some(avatar) = avatar {
+            if let Ok(bytes) = std::fs::read(PathBuf::from("./assets").join(&avatar)) {
+                zip.start_file(avatar, options)?;
+                zip.write_all(&bytes)?;
+            }
+        }
+        zip.finish()?;
+        Ok(())
+    })
+    .await??;
+
+    Ok(file_name)
+}
+
+async fn remove_expired_exports(data: &AppState) -> Result<(), sqlx::Error> {
+    let expired = sqlx::query_scalar!(
+        "DELETE FROM data_exports WHERE expires_at <= NOW() RETURNING file_name"
+    )
+    .fetch_all(&data.db)
+    .await?;
+
+    for file_name in expired.into_iter().flatten() {
+        remove_file(PathBuf::from(EXPORTS_DIR).join(file_name));
+    }
+    Ok(())
+}
+
+/// Comment streams can only resume within a day of losing the connection.
+async fn remove_old_comment_events(data: &AppState) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM comment_events WHERE created_at <= NOW() - INTERVAL '1 day'")
+        .execute(&data.db)
+        .await?;
+    Ok(())
+}
+
+/// Scheduled posts that failed to publish this many times go back to drafts.
+const MAX_PUBLISH_ATTEMPTS: i32 = 5;
+
+/// Publishes the scheduled posts that are due, whether or not their authors are
+/// online. Each post is claimed with SKIP LOCKED so that several server
+/// instances never publish the same post twice.
+async fn publish_scheduled_posts(data: &AppState) -> Result<(), AppError> {
+    // posts that failed during this run, retried on the next one
+    let mut failed = Vec::new();
+    loop {
+        let mut tx = data
+            .db
+            .begin()
+            .await
+            .map_err(|_| AppError::InternalServerError)?;
+        let due = sqlx::query!(
+            "SELECT id, user_id FROM posts
+            WHERE status = 'scheduled' AND publish_at <= NOW() AND id <> ALL($1)
+            ORDER BY publish_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED",
+            &failed
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+        let Some(due) = due else {
+            return Ok(());
+        };
+
+        let notified = match publish_post(data, &mut tx, due.id).await {
+            Ok(notified) => Some(notified),
+            // the author left the community or lost access to the quoted post
+            // since scheduling, the post goes back to their drafts
+            Err(AppError::JsendFail(reason)) => {
+                tracing::warn!("scheduled post {} returned to drafts: {}", due.id, reason);
+                sqlx::query!(
+                    "UPDATE posts SET status = $2, publish_at = NULL WHERE id = $1",
+                    due.id,
+                    PostStatus::Draft.as_str()
+                )
+                .execute(&mut *tx)
+                .await
+                .map_err(|_| AppError::InternalServerError)?;
+                None
+            }
+            Err(err) => {
+                drop(tx);
+                tracing::error!("failed to publish scheduled post {}: {}", due.id, err);
+                failed.push(due.id);
+                if let Err(err) = record_failed_publication(&data.db, due.id).await {
+                    tracing::error!("failed to record the attempt on post {}: {}", due.id, err);
+                }
+                continue;
+            }
+        };
+        tx.commit()
+            .await
+            .map_err(|_| AppError::InternalServerError)?;
+        if let Some(notified) = notified {
+            announce(data, due.id, due.user_id, notified).await;
+        }
+    }
+}
+
+/// Counts a failed attempt to publish the post, and returns it to the author's
+/// drafts once it keeps failing.
+async fn record_failed_publication(db: &PgPool, post_id: Uuid) -> Result<(), sqlx::Error> {
+    let returned = sqlx::query_scalar!(
+        "UPDATE posts SET publish_attempts = publish_attempts + 1,
+            status = CASE WHEN publish_attempts + 1 >= $2 THEN $3 ELSE status END,
+            publish_at = CASE WHEN publish_attempts + 1 >= $2 THEN NULL ELSE publish_at END
+        WHERE id = $1 AND status = 'scheduled'
+        RETURNING status = $3 AS \"returned!\"",
+        post_id,
+        MAX_PUBLISH_ATTEMPTS,
+        PostStatus::Draft.as_str()
+    )
+    .fetch_optional(db)
+    .await?;
+    if returned == Some(true) {
+        tracing::warn!(
+            "scheduled post {} returned to drafts after {} failed attempts",
+            post_id,
+            MAX_PUBLISH_ATTEMPTS
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::auth_handlers::create_user_with_profile;
+
+    #[sqlx::test]
+    async fn failing_scheduled_posts_return_to_drafts(db: PgPool) {
+        let mut tx = db.begin().await.unwrap();
+        let user_id = create_user_with_profile(&mut tx, "author", "author@example.com", None)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+        let post_id = sqlx::query_scalar!(
+            "INSERT INTO posts (user_id, title, content, status, publish_at)
+            VALUES ($1, 'title', 'content', 'scheduled', NOW()) RETURNING id",
+            user_id
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+
+        for attempt in 1..=MAX_PUBLISH_ATTEMPTS {
+            record_failed_publication(&db, post_id).await.unwrap();
+            let post = sqlx::query!(
+                "SELECT status, publish_at, publish_attempts FROM posts WHERE id = $1",
+                post_id
+            )
+            .fetch_one(&db)
+            .await
+            .unwrap();
+            assert_eq!(post.publish_attempts, attempt);
+            let gave_up = attempt == MAX_PUBLISH_ATTEMPTS;
+            assert_eq!(post.status == PostStatus::Draft.as_str(), gave_up);
+            assert_eq!(post.publish_at.is_none(), gave_up);
+        }
+    }
+}
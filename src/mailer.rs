@@ -0,0 +1,36 @@
+use crate::{config::MailConfig, response::AppError};
+use lettre::{message::Mailbox, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+/// Sends account emails over SMTP, only present when a mail server is configured.
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn new(config: &MailConfig) -> Result<Mailer, String> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(&config.smtp_url)
+            .map_err(|err| err.to_string())?
+            .build();
+        let from = config
+            .from
+            .parse()
+            .map_err(|err: lettre::address::AddressError| err.to_string())?;
+        Ok(Mailer { transport, from })
+    }
+
+    pub async fn send(&self, to: &str, subject: &str, body: String) -> Result<(), AppError> {
+        let to: Mailbox = to.parse().map_err(|_| AppError::InternalServerError)?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .body(body)
+            .map_err(|_| AppError::InternalServerError)?;
+        self.transport
+            .send(message)
+            .await
+            .map_err(|_| AppError::JsendError("mail delivery failed".to_string()))?;
+        Ok(())
+    }
+}
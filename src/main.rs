@@ -1,11 +1,22 @@
 mod config;
 mod filters;
 mod handlers;
+mod jobs;
+mod mailer;
+mod mentions;
 mod model;
+mod notifications;
+mod oidc;
+mod password_hashing;
+mod password_policy;
+mod publishing;
+mod realtime;
 mod response;
 mod route;
 mod schema;
 mod session_auth;
+mod tags;
+mod tokens;
 mod validation;
 
 use axum::http::{
@@ -14,6 +25,10 @@ use axum::http::{
 };
 use config::Config;
 use dotenv::dotenv;
+use mailer::Mailer;
+use password_hashing::PasswordHashing;
+use password_policy::PasswordPolicy;
+use realtime::Realtime;
 use route::create_router;
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
 use std::sync::Arc;
@@ -22,11 +37,18 @@ use tower_http::trace::TraceLayer;
 use tower_http::{cors::CorsLayer, services::ServeDir};
 use tower_sessions::{cookie::SameSite, Expiry, SessionManagerLayer};
 use tower_sessions_redis_store::{fred::prelude::*, RedisStore};
+use webauthn_rs::{prelude::Url, Webauthn, WebauthnBuilder};
 
 #[allow(dead_code)]
 pub struct AppState {
     db: Pool<Postgres>,
     env: Config,
+    http: reqwest::Client,
+    webauthn: Webauthn,
+    password_policy: PasswordPolicy,
+    hashing: PasswordHashing,
+    realtime: Realtime,
+    mailer: Option<Mailer>,
 }
 
 #[tokio::main]
@@ -61,9 +83,45 @@ async fn main() {
         }
     };
 
+    let webauthn = match Url::parse(&config.webauthn_rp_origin)
+        .map_err(|err| err.to_string())
+        .and_then(|origin| {
+            WebauthnBuilder::new(&config.webauthn_rp_id, &origin)
+                .and_then(|builder| builder.rp_name("Blaze").build())
+                .map_err(|err| err.to_string())
+        }) {
+        Ok(webauthn) => webauthn,
+        Err(err) => {
+            println!("🔥 Invalid webauthn configuration: {:?}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let hashing = match PasswordHashing::new(&config.argon2) {
+        Ok(hashing) => hashing,
+        Err(err) => {
+            println!("🔥 Invalid argon2 configuration: {:?}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let mailer = match config.mail.as_ref().map(Mailer::new).transpose() {
+        Ok(mailer) => mailer,
+        Err(err) => {
+            println!("🔥 Invalid mail configuration: {:?}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let realtime = Realtime::new(redis_pool.clone());
+
     println!("✅ Server started successfully");
 
     let redis_conn = redis_pool.connect();
+    if let Err(err) = realtime.listen(RedisConfig::default()).await {
+        println!("🔥 Failed to subscribe to redis: {:?}", err);
+        std::process::exit(1);
+    }
 
     let session_store = RedisStore::new(redis_pool);
     let session_layer = SessionManagerLayer::new(session_store)
@@ -81,14 +139,24 @@ async fn main() {
         .with_max_level(tracing::Level::DEBUG)
         .init();
 
-    let app = create_router(Arc::new(AppState {
+    let app_state = Arc::new(AppState {
         db: pool.clone(),
         env: config.clone(),
-    }))
-    .nest_service("/assets", ServeDir::new("./assets"))
-    .layer(cors)
-    .layer(session_layer)
-    .layer(TraceLayer::new_for_http());
+        http: reqwest::Client::new(),
+        webauthn,
+        password_policy: PasswordPolicy::new(&config.password_policy),
+        hashing,
+        realtime,
+        mailer,
+    });
+
+    jobs::spawn(app_state.clone());
+
+    let app = create_router(app_state)
+        .nest_service("/assets", ServeDir::new("./assets"))
+        .layer(cors)
+        .layer(session_layer)
+        .layer(TraceLayer::new_for_http());
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
     axum::serve(listener, app).await.unwrap();
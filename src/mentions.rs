@@ -0,0 +1,176 @@
+use crate::response::AppError;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+pub const MAX_MENTIONS: usize = 10;
+
+fn is_username_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Splits content into plain text and `@username` mentions. A mention has to
+/// start the content or follow a character that cannot be part of a username,
+/// so email addresses are left alone.
+fn tokenize(content: &str) -> Vec<(bool, &str)> {
+    let mut tokens = Vec::new();
+    let mut text_start = 0;
+    let mut chars = content.char_indices().peekable();
+    let mut previous: Option<char> = None;
+
+    while let Some((index, c)) = chars.next() {
+        if c == '@' && !previous.is_some_and(is_username_char) {
+            let name_start = index + 1;
+            let mut name_end = name_start;
+            while let Some(&(next_index, next)) = chars.peek() {
+                if !is_username_char(next) {
+                    break;
+                }
+                name_end = next_index + next.len_utf8();
+                chars.next();
+            }
+            if name_end > name_start {
+                tokens.push((false, &content[text_start..index]));
+                tokens.push((true, &content[name_start..name_end]));
+                text_start = name_end;
+                previous = content[name_start..name_end].chars().last();
+                continue;
+            }
+        }
+        previous = Some(c);
+    }
+    tokens.push((false, &content[text_start..]));
+    tokens
+}
+
+/// Usernames mentioned in the content, without duplicates.
+pub fn extract_mentions(content: &str) -> Vec<String> {
+    let mut usernames: Vec<String> = Vec::new();
+    for (_, username) in tokenize(content)
+        .into_iter()
+        .filter(|(mention, _)| *mention)
+    {
+        if !usernames.iter().any(|existing| existing == username) {
+            usernames.push(username.to_string());
+        }
+    }
+    usernames.truncate(MAX_MENTIONS);
+    usernames
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// HTML escaped content with the mentions of existing users turned into links
+/// to their profiles. `mentioned` holds the usernames recorded for the content.
+pub fn render_mentions(content: &str, mentioned: &[String]) -> String {
+    tokenize(content)
+        .into_iter()
+        .map(|(mention, text)| {
+            let linked = mention && mentioned.iter().any(|username| username == text);
+            let text = escape_html(text);
+            if linked {
+                format!("<a href=\"/user/{}\">@{}</a>", text, text)
+            } else if mention {
+                format!("@{}", text)
+            } else {
+                text
+            }
+        })
+        .collect()
+}
+
+/// Stores the mentions in a post or comment and returns the mentioned user ids.
+/// The author and users on either side of a block with the author are skipped.
+pub async fn save_mentions(
+    tx: &mut Transaction<'_, Postgres>,
+    author_id: Uuid,
+    post_id: Uuid,
+    comment_id: Option<Uuid>,
+    content: &str,
+) -> Result<Vec<Uuid>, AppError> {
+    let usernames = extract_mentions(content);
+    if usernames.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    sqlx::query_scalar!(
+        "INSERT INTO mentions (user_id, author_id, post_id, comment_id)
+        SELECT users.id, $1, $2, $3 FROM users
+        WHERE users.username = ANY($4)
+            AND users.id <> $1
+            AND NOT EXISTS (
+                SELECT 1 FROM blocks
+                WHERE (blocker_id = $1 AND blocked_id = users.id)
+                    OR (blocker_id = users.id AND blocked_id = $1)
+            )
+        RETURNING user_id",
+        author_id,
+        post_id,
+        comment_id,
+        &usernames
+    )
+    .fetch_all(&mut **tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_splits_mentions_from_text() {
+        assert_eq!(
+            tokenize("hi @alice, and @bob_2!"),
+            [
+                (false, "hi "),
+                (true, "alice"),
+                (false, ", and "),
+                (true, "bob_2"),
+                (false, "!"),
+            ]
+        );
+        assert_eq!(
+            tokenize("@alice"),
+            [(false, ""), (true, "alice"), (false, "")]
+        );
+    }
+
+    #[test]
+    fn email_addresses_are_not_mentions() {
+        assert_eq!(
+            tokenize("mail alice@example.com"),
+            [(false, "mail alice@example.com")]
+        );
+        assert!(extract_mentions("write to bob@example.com or @ alone").is_empty());
+    }
+
+    #[test]
+    fn extract_mentions_skips_duplicates() {
+        assert_eq!(extract_mentions("@alice @bob @alice"), ["alice", "bob"]);
+        let many: String = (0..15).map(|i| format!("@user{} ", i)).collect();
+        assert_eq!(extract_mentions(&many).len(), MAX_MENTIONS);
+    }
+
+    #[test]
+    fn render_mentions_links_only_recorded_users() {
+        let mentioned = vec!["alice".to_string()];
+        assert_eq!(
+            render_mentions("@alice and @nobody", &mentioned),
+            "<a href=\"/user/alice\">@alice</a> and @nobody"
+        );
+    }
+
+    #[test]
+    fn render_mentions_escapes_html() {
+        assert_eq!(
+            render_mentions("<script>\"x\" & 'y'</script> @alice", &[]),
+            "&lt;script&gt;&quot;x&quot; &amp; &#39;y&#39;&lt;/script&gt; @alice"
+        );
+    }
+}
@@ -1,17 +1,39 @@
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use serde::{Deserialize, Serialize, Serializer};
+use sqlx::{types::Json, FromRow};
 use uuid::Uuid;
 
+/// Post or comment content that serializes as HTML with mentions linked to
+/// the mentioned profiles. Only the usernames recorded as mentions are linked,
+/// so an `@name` that matched nobody stays plain text.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ContentHtml {
+    pub content: String,
+    pub mentions: Vec<String>,
+}
+
+impl Serialize for ContentHtml {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&crate::mentions::render_mentions(
+            &self.content,
+            &self.mentions,
+        ))
+    }
+}
+
 #[derive(Debug, Deserialize, FromRow, Serialize, Clone)]
 pub struct UserModel {
     pub id: Option<Uuid>,
     pub username: String,
     pub email: String,
-    pub password: String,
+    /// None for accounts created through social login that never set one.
+    pub password: Option<String>,
     pub role: String,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
+    pub suspended_at: Option<DateTime<Utc>>,
+    /// Sessions started under an older epoch are no longer valid.
+    pub session_epoch: i32,
 }
 
 #[derive(Debug, Deserialize, FromRow, Serialize, Clone)]
@@ -20,6 +42,11 @@ pub struct ProfileModel {
     pub user_id: Uuid,
     pub profile_image: String,
     pub bio: String,
+    pub display_name: String,
+    pub website: String,
+    pub location: String,
+    pub pronouns: String,
+    pub is_private: bool,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
 }
@@ -32,6 +59,12 @@ pub struct PostModel {
     pub content: String,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
+    pub community_id: Option<Uuid>,
+    pub repost_of_id: Option<Uuid>,
+    pub quote_of_id: Option<Uuid>,
+    pub pinned_at: Option<DateTime<Utc>>,
+    pub status: String,
+    pub publish_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Deserialize, FromRow, Serialize, Clone)]
@@ -49,7 +82,7 @@ pub struct ReactionModel {
     pub id: Option<Uuid>,
     pub user_id: Uuid,
     pub post_id: Uuid,
-    pub reaction_type: String,
+    pub reaction_type: bool,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
 }
@@ -80,22 +113,58 @@ pub struct ReactionModel {
 //pub exp: usize,
 //}
 
+#[allow(dead_code)]
 #[derive(Debug, Deserialize, FromRow, Serialize, Clone)]
 pub struct Register {
     pub id: Uuid,
     pub password: String,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EmbeddedPost {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub display_name: String,
+    pub profile_image: String,
+    pub title: String,
+    pub content: String,
+    pub content_html: ContentHtml,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Built by the post_response SQL function.
+#[derive(Deserialize, Serialize)]
 pub struct PostResponse {
     pub id: Uuid,
     pub user_id: Uuid,
+    pub community_id: Option<Uuid>,
+    pub repost_of_id: Option<Uuid>,
+    pub quote_of_id: Option<Uuid>,
+    pub pinned_at: Option<DateTime<Utc>>,
+    pub status: String,
+    pub publish_at: Option<DateTime<Utc>>,
+    /// The reposted or quoted post, null when it was deleted or the caller
+    /// may not see it.
+    pub original: Option<EmbeddedPost>,
+    /// Only counts the reposts and quotes the caller may see.
+    pub repost_count: i64,
+    pub quote_count: i64,
     pub username: String,
+    pub display_name: String,
+    pub pronouns: String,
+    pub bio: String,
+    pub website: String,
+    pub location: String,
     pub profile_image: String,
     pub title: String,
     pub content: String,
+    pub content_html: ContentHtml,
     pub likes: Option<i64>,
     pub dislikes: Option<i64>,
+    /// Only present when the caller is logged in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_bookmarked: Option<bool>,
     pub updated_at: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
 }
@@ -104,10 +173,16 @@ pub struct PostResponse {
 pub struct CommentResponse {
     pub id: Option<Uuid>,
     pub username: String,
+    pub display_name: String,
+    pub pronouns: String,
+    pub bio: String,
+    pub website: String,
+    pub location: String,
     pub profile_image: String,
     pub user_id: Uuid,
     pub post_id: Uuid,
     pub content: String,
+    pub content_html: Json<ContentHtml>,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
 }
@@ -117,8 +192,10 @@ pub struct UserResponse {
     pub id: Option<Uuid>,
     pub username: String,
     pub email: String,
+    pub role: String,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
+    pub suspended_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -126,4 +203,133 @@ pub struct ProfileResponse {
     pub profile_id: Option<Uuid>,
     pub username: String,
     pub profile_image: String,
+    pub display_name: String,
+    pub bio: String,
+    pub website: String,
+    pub location: String,
+    pub pronouns: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PasskeyResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DataExportResponse {
+    pub id: Uuid,
+    pub status: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PostSearchResult {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub display_name: String,
+    pub profile_image: String,
+    pub title: String,
+    pub content: String,
+    pub title_highlight: String,
+    pub content_highlight: String,
+    pub rank: f32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CommentSearchResult {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub post_id: Uuid,
+    pub username: String,
+    pub display_name: String,
+    pub profile_image: String,
+    pub content: String,
+    pub content_highlight: String,
+    pub rank: f32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TrendingTagResponse {
+    pub name: String,
+    pub post_count: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MentionResponse {
+    pub id: Uuid,
+    pub post_id: Uuid,
+    pub comment_id: Option<Uuid>,
+    pub author_id: Uuid,
+    pub username: String,
+    pub display_name: String,
+    pub profile_image: String,
+    pub content: String,
+    pub content_html: Json<ContentHtml>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NotificationResponse {
+    pub id: Uuid,
+    pub kind: String,
+    pub post_id: Option<Uuid>,
+    pub comment_id: Option<Uuid>,
+    pub actor_count: i64,
+    pub actors: Vec<String>,
+    pub read_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ConversationResponse {
+    pub id: Uuid,
+    pub title: Option<String>,
+    pub is_group: bool,
+    pub members: Vec<String>,
+    pub last_message: Option<String>,
+    pub last_message_at: Option<DateTime<Utc>>,
+    pub unread_count: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MessageResponse {
+    pub id: Uuid,
+    pub seq: i64,
+    pub conversation_id: Uuid,
+    pub sender_id: Uuid,
+    pub username: String,
+    pub display_name: String,
+    pub profile_image: String,
+    pub content: String,
+    pub read_by: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CommunityResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub description: String,
+    pub member_count: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CommunityMemberResponse {
+    pub username: String,
+    pub display_name: String,
+    pub profile_image: String,
+    pub role: String,
+    pub joined_at: DateTime<Utc>,
 }
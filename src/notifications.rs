@@ -0,0 +1,80 @@
+use crate::response::AppError;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationKind {
+    Comment,
+    Like,
+    Follow,
+    FollowRequest,
+    Mention,
+    Repost,
+    Quote,
+}
+
+impl NotificationKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NotificationKind::Comment => "comment",
+            NotificationKind::Like => "like",
+            NotificationKind::Follow => "follow",
+            NotificationKind::FollowRequest => "follow_request",
+            NotificationKind::Mention => "mention",
+            NotificationKind::Repost => "repost",
+            NotificationKind::Quote => "quote",
+        }
+    }
+
+    /// Events of the same kind on the same target are batched into one unread
+    /// notification, e.g. "12 people liked your post". Mentions and quotes always
+    /// stand alone.
+    fn group_key(self, post_id: Option<Uuid>) -> Option<String> {
+        match self {
+            NotificationKind::Comment | NotificationKind::Like | NotificationKind::Repost => {
+                post_id.map(|post_id| post_id.to_string())
+            }
+            NotificationKind::Follow | NotificationKind::FollowRequest => {
+                Some(self.as_str().to_string())
+            }
+            NotificationKind::Mention | NotificationKind::Quote => None,
+        }
+    }
+}
+
+/// Records that the actor did something the user should hear about. Nothing is
+/// recorded for the user's own actions or for actors the user has muted, which
+/// is reported by returning false.
+pub async fn notify(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    actor_id: Uuid,
+    kind: NotificationKind,
+    post_id: Option<Uuid>,
+    comment_id: Option<Uuid>,
+) -> Result<bool, AppError> {
+    let recorded = sqlx::query!(
+        "WITH notification AS (
+            INSERT INTO notifications (user_id, kind, post_id, comment_id, group_key)
+            SELECT $1::uuid, $3::text, $4::uuid, $5::uuid, $6::text
+            WHERE $1 <> $2::uuid
+                AND NOT EXISTS (SELECT 1 FROM mutes WHERE muter_id = $1 AND muted_id = $2)
+            ON CONFLICT (user_id, kind, group_key) WHERE read_at IS NULL
+            DO UPDATE SET comment_id = EXCLUDED.comment_id, updated_at = NOW()
+            RETURNING id
+        )
+        INSERT INTO notification_actors (notification_id, actor_id)
+        SELECT id, $2 FROM notification
+        ON CONFLICT (notification_id, actor_id) DO UPDATE SET created_at = NOW()",
+        user_id,
+        actor_id,
+        kind.as_str(),
+        post_id,
+        comment_id,
+        kind.group_key(post_id)
+    )
+    .execute(executor)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    Ok(recorded.rows_affected() > 0)
+}
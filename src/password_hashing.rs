@@ -0,0 +1,112 @@
+use crate::{config::Argon2Config, response::AppError};
+use argon2::{
+    password_hash::{rand_core::OsRng, SaltString},
+    Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version,
+};
+
+pub struct PasswordHashing {
+    argon2: Argon2<'static>,
+}
+
+impl PasswordHashing {
+    pub fn new(config: &Argon2Config) -> Result<PasswordHashing, argon2::Error> {
+        let params = Params::new(
+            config.memory_kib,
+            config.iterations,
+            config.parallelism,
+            None,
+        )?;
+        Ok(PasswordHashing {
+            argon2: Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+        })
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, AppError> {
+        let salt = SaltString::generate(&mut OsRng);
+        self.argon2
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|_| AppError::InternalServerError)
+            .map(|hash| hash.to_string())
+    }
+
+    /// Verifies against the parameters recorded in the hash itself, so hashes
+    /// made with older settings keep working. Accounts without a password
+    /// (social login) have no hash and are never verified against.
+    pub fn verify(&self, password: &str, hash: &str) -> bool {
+        match PasswordHash::new(hash) {
+            Ok(parsed_hash) => self
+                .argon2
+                .verify_password(password.as_bytes(), &parsed_hash)
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Whether a hash was made with another algorithm, an older version or
+    /// weaker parameters than the current configuration.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        let Ok(parsed_hash) = PasswordHash::new(hash) else {
+            return false;
+        };
+        if parsed_hash.algorithm != Algorithm::Argon2id.ident()
+            || parsed_hash.version != Some(Version::V0x13.into())
+        {
+            return true;
+        }
+        let current = self.argon2.params();
+        match Params::try_from(&parsed_hash) {
+            Ok(params) => {
+                params.m_cost() < current.m_cost()
+                    || params.t_cost() < current.t_cost()
+                    || params.p_cost() < current.p_cost()
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashing(memory_kib: u32, iterations: u32) -> PasswordHashing {
+        PasswordHashing::new(&Argon2Config {
+            memory_kib,
+            iterations,
+            parallelism: 1,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn current_hashes_need_no_rehash() {
+        let hashing = hashing(8192, 2);
+        let hash = hashing.hash("correct horse").unwrap();
+        assert!(hashing.verify("correct horse", &hash));
+        assert!(!hashing.needs_rehash(&hash));
+    }
+
+    #[test]
+    fn weaker_parameters_need_a_rehash() {
+        let hash = hashing(8192, 1).hash("correct horse").unwrap();
+        assert!(hashing(8192, 2).needs_rehash(&hash));
+        assert!(hashing(16384, 1).needs_rehash(&hash));
+        // stronger hashes are kept when the configuration is lowered
+        assert!(!hashing(4096, 1).needs_rehash(&hash));
+    }
+
+    #[test]
+    fn other_algorithms_need_a_rehash() {
+        let salt = SaltString::generate(&mut OsRng);
+        let argon2i = Argon2::new(Algorithm::Argon2i, Version::V0x13, Params::default())
+            .hash_password(b"correct horse", &salt)
+            .unwrap()
+            .to_string();
+        assert!(hashing(8192, 2).needs_rehash(&argon2i));
+    }
+
+    #[test]
+    fn missing_hashes_are_left_alone() {
+        assert!(!hashing(8192, 2).needs_rehash(""));
+    }
+}
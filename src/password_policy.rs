@@ -0,0 +1,170 @@
+use crate::{config::PasswordPolicyConfig, response::AppError};
+use serde_json::json;
+use std::collections::HashSet;
+use zxcvbn::zxcvbn;
+
+/// Keeps hashing cheap for absurdly long inputs, long passphrases still fit.
+const MAX_PASSWORD_LENGTH: usize = 128;
+
+pub struct PasswordPolicy {
+    min_length: usize,
+    min_score: u8,
+    min_character_classes: usize,
+    breached: HashSet<String>,
+}
+
+impl PasswordPolicy {
+    pub fn new(config: &PasswordPolicyConfig) -> PasswordPolicy {
+        // the list holds one password per line, compared case insensitively
+        let breached = match std::fs::read_to_string(&config.breached_list_path) {
+            Ok(contents) => contents
+                .lines()
+                .map(|line| line.trim().to_lowercase())
+                .filter(|line| !line.is_empty())
+                .collect(),
+            Err(err) => {
+                tracing::warn!(
+                    "breached password list {} not loaded: {}",
+                    config.breached_list_path,
+                    err
+                );
+                HashSet::new()
+            }
+        };
+
+        PasswordPolicy {
+            min_length: config.min_length,
+            min_score: config.min_score,
+            min_character_classes: config.min_character_classes,
+            breached,
+        }
+    }
+
+    /// Checks a new password against the policy. `user_inputs` are the account's
+    /// username and email, which the password may not contain.
+    pub fn check(&self, password: &str, user_inputs: &[&str]) -> Result<(), AppError> {
+        self.violation(password, user_inputs)
+            .map_or(Ok(()), |message| {
+                Err(AppError::JsendFail(json!({"password" : message})))
+            })
+    }
+
+    fn violation(&self, password: &str, user_inputs: &[&str]) -> Option<&'static str> {
+        let length = password.chars().count();
+        if length == 0 {
+            return Some("password cannot be empty");
+        }
+        if length < self.min_length {
+            return Some("password too short");
+        }
+        if length > MAX_PASSWORD_LENGTH {
+            return Some("password too long");
+        }
+
+        let classes = [
+            password.chars().any(|c| c.is_lowercase()),
+            password.chars().any(|c| c.is_uppercase()),
+            password.chars().any(|c| c.is_ascii_digit()),
+            password.chars().any(|c| !c.is_alphanumeric()),
+        ];
+        if classes.iter().filter(|present| **present).count() < self.min_character_classes {
+            return Some("password needs more types of characters");
+        }
+
+        let lowered = password.to_lowercase();
+        let contains_user_input = user_inputs
+            .iter()
+            .flat_map(|input| [*input, input.split('@').next().unwrap_or_default()])
+            .map(|input| input.to_lowercase())
+            .any(|input| input.len() >= 3 && lowered.contains(&input));
+        if contains_user_input {
+            return Some("password cannot contain your username or email");
+        }
+
+        if self.breached.contains(&lowered) {
+            return Some("password has appeared in a data breach");
+        }
+
+        if (zxcvbn(password, user_inputs).score() as u8) < self.min_score {
+            return Some("password is too weak");
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 8,
+            min_score: 2,
+            min_character_classes: 2,
+            breached: ["correcthorse1!".to_string()].into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn accepts_a_strong_password() {
+        assert_eq!(
+            policy().violation("violet Tractor 93 sings", &["someone"]),
+            None
+        );
+    }
+
+    #[test]
+    fn enforces_the_length_limits() {
+        let policy = policy();
+        assert_eq!(policy.violation("", &[]), Some("password cannot be empty"));
+        assert_eq!(policy.violation("aB3$", &[]), Some("password too short"));
+        let long = "aB3$".repeat(MAX_PASSWORD_LENGTH / 4 + 1);
+        assert_eq!(policy.violation(&long, &[]), Some("password too long"));
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        // eight characters but sixteen bytes
+        assert_ne!(
+            policy().violation("ééééééé1", &[]),
+            Some("password too short")
+        );
+    }
+
+    #[test]
+    fn requires_enough_character_classes() {
+        assert_eq!(
+            policy().violation("lowercaseonly", &[]),
+            Some("password needs more types of characters")
+        );
+    }
+
+    #[test]
+    fn rejects_the_username_and_email() {
+        let policy = policy();
+        let message = Some("password cannot contain your username or email");
+        assert_eq!(policy.violation("xSomeone42!", &["someone"]), message);
+        // the local part of the email counts on its own
+        assert_eq!(
+            policy.violation("Mailbox#2024", &["someone", "mailbox@example.com"]),
+            message
+        );
+    }
+
+    #[test]
+    fn rejects_breached_passwords_case_insensitively() {
+        assert_eq!(
+            policy().violation("CorrectHorse1!", &[]),
+            Some("password has appeared in a data breach")
+        );
+    }
+
+    #[test]
+    fn rejects_weak_passwords() {
+        assert_eq!(
+            policy().violation("Password1", &[]),
+            Some("password is too weak")
+        );
+    }
+}
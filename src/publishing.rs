@@ -0,0 +1,173 @@
+use crate::{
+    handlers::{follow_handlers::can_view_posts, post_handlers::check_publishable},
+    mentions::save_mentions,
+    notifications::{notify, NotificationKind},
+    realtime::{Audience, RealtimeEvent},
+    response::AppError,
+    AppState,
+};
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostStatus {
+    Draft,
+    Scheduled,
+    Published,
+}
+
+impl PostStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PostStatus::Draft => "draft",
+            PostStatus::Scheduled => "scheduled",
+            PostStatus::Published => "published",
+        }
+    }
+}
+
+/// Records what only happens once a post goes out: its mentions and the
+/// notifications for them and for the quoted author. Mentioned users who may not
+/// see the post are not notified. Returns who was notified so the caller can
+/// push the notifications after committing.
+pub async fn record_publication(
+    data: &AppState,
+    tx: &mut Transaction<'_, Postgres>,
+    post_id: Uuid,
+    author_id: Uuid,
+    content: &str,
+    quoted_author: Option<Uuid>,
+) -> Result<Vec<(Uuid, NotificationKind)>, AppError> {
+    let mut notified = Vec::new();
+    for mentioned in save_mentions(tx, author_id, post_id, None, content).await? {
+        if !can_view_posts(data, Some(mentioned), author_id).await? {
+            continue;
+        }
+        let kind = NotificationKind::Mention;
+        if notify(&mut **tx, mentioned, author_id, kind, Some(post_id), None).await? {
+            notified.push((mentioned, kind));
+        }
+    }
+    if let Some(quoted_author) = quoted_author {
+        let kind = NotificationKind::Quote;
+        if notify(
+            &mut **tx,
+            quoted_author,
+            author_id,
+            kind,
+            Some(post_id),
+            None,
+        )
+        .await?
+        {
+            notified.push((quoted_author, kind));
+        }
+    }
+    Ok(notified)
+}
+
+/// Publishes a draft or scheduled post. The post is dated to the moment it goes
+/// out so it lands at the top of feeds, and its tags start counting towards the
+/// trending window now. Publishing an already published post does nothing.
+/// Fails with the same errors as creating the post when the author has left or
+/// was banned from the community, or the quoted post can no longer be shared.
+pub async fn publish_post(
+    data: &AppState,
+    tx: &mut Transaction<'_, Postgres>,
+    post_id: Uuid,
+) -> Result<Vec<(Uuid, NotificationKind)>, AppError> {
+    let post = sqlx::query!(
+        "SELECT user_id, content, community_id, quote_of_id FROM posts
+        WHERE id = $1 AND status <> $2
+        FOR UPDATE",
+        post_id,
+        PostStatus::Published.as_str()
+    )
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    let Some(post) = post else {
+        return Ok(Vec::new());
+    };
+    let quoted = check_publishable(data, post.user_id, post.community_id, post.quote_of_id).await?;
+
+    sqlx::query!(
+        "UPDATE posts SET status = $2, created_at = NOW(), updated_at = NOW() WHERE id = $1",
+        post_id,
+        PostStatus::Published.as_str()
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    sqlx::query!(
+        "UPDATE post_tags SET created_at = NOW() WHERE post_id = $1",
+        post_id
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    record_publication(
+        data,
+        tx,
+        post_id,
+        post.user_id,
+        &post.content,
+        quoted.map(|(_, quoted_author)| quoted_author),
+    )
+    .await
+}
+
+/// Who gets a new post by the author pushed: followers and the author for
+/// private accounts, otherwise everyone except users on either side of a block
+/// and users who muted the author.
+async fn post_audience(data: &AppState, author_id: Uuid) -> Result<Audience, sqlx::Error> {
+    let audience = sqlx::query!(
+        r#"SELECT
+            COALESCE((SELECT is_private FROM profiles WHERE user_id = $1), FALSE) AS "is_private!",
+            ARRAY(SELECT follower_id FROM follows WHERE followee_id = $1) AS "followers!",
+            ARRAY(
+                SELECT blocked_id FROM blocks WHERE blocker_id = $1
+                UNION SELECT blocker_id FROM blocks WHERE blocked_id = $1
+                UNION SELECT muter_id FROM mutes WHERE muted_id = $1
+            ) AS "hidden!""#,
+        author_id
+    )
+    .fetch_one(&data.db)
+    .await?;
+
+    let only = audience.is_private.then(|| {
+        let mut followers = audience.followers;
+        followers.push(author_id);
+        followers
+    });
+    Ok(Audience {
+        only,
+        except: audience.hidden,
+    })
+}
+
+/// Pushes a freshly published post and the notifications it caused to the
+/// connected clients. Call after the publishing transaction committed.
+pub async fn announce(
+    data: &AppState,
+    post_id: Uuid,
+    user_id: Uuid,
+    notified: Vec<(Uuid, NotificationKind)>,
+) {
+    match post_audience(data, user_id).await {
+        Ok(audience) => {
+            data.realtime
+                .publish_to(RealtimeEvent::NewPost { post_id, user_id }, audience)
+                .await
+        }
+        Err(err) => tracing::error!("failed to find the audience of post {}: {}", post_id, err),
+    }
+    for (recipient, kind) in notified {
+        data.realtime
+            .publish(RealtimeEvent::notification(recipient, kind))
+            .await;
+    }
+}
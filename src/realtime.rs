@@ -0,0 +1,228 @@
+use crate::notifications::NotificationKind;
+use fred::{prelude::*, types::Builder};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+/// Redis channel every server instance publishes to and listens on.
+const CHANNEL: &str = "realtime";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommentAction {
+    Created,
+    Edited,
+    Deleted,
+}
+
+impl CommentAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CommentAction::Created => "created",
+            CommentAction::Edited => "edited",
+            CommentAction::Deleted => "deleted",
+        }
+    }
+
+    pub fn parse(action: &str) -> Option<CommentAction> {
+        match action {
+            "created" => Some(CommentAction::Created),
+            "edited" => Some(CommentAction::Edited),
+            "deleted" => Some(CommentAction::Deleted),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RealtimeEvent {
+    NewPost {
+        post_id: Uuid,
+        user_id: Uuid,
+    },
+    Comment {
+        event_id: i64,
+        post_id: Uuid,
+        comment_id: Uuid,
+        user_id: Uuid,
+        action: CommentAction,
+    },
+    Reactions {
+        post_id: Uuid,
+        likes: Option<i64>,
+        dislikes: Option<i64>,
+    },
+    Notification {
+        user_id: Uuid,
+        kind: String,
+    },
+    Message {
+        conversation_id: Uuid,
+        seq: i64,
+        sender_id: Uuid,
+    },
+    MessagesRead {
+        conversation_id: Uuid,
+        user_id: Uuid,
+        seq: i64,
+    },
+    /// A block or privacy change between these users, connections recheck the
+    /// posts they are subscribed to. Never forwarded to clients.
+    VisibilityChanged {
+        user_ids: Vec<Uuid>,
+    },
+    /// Sessions of the user from before this epoch ended, connections opened
+    /// with them close. Never forwarded to clients.
+    SessionsEnded {
+        user_id: Uuid,
+        session_epoch: i32,
+    },
+}
+
+/// Who may receive an event. Worked out once by the publisher so that the
+/// connections do not query the database for every event.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Audience {
+    /// Only these users, everyone when None.
+    pub only: Option<Vec<Uuid>>,
+    /// Never these users.
+    pub except: Vec<Uuid>,
+}
+
+impl Audience {
+    pub fn includes(&self, user_id: Uuid) -> bool {
+        self.only
+            .as_ref()
+            .is_none_or(|only| only.contains(&user_id))
+            && !self.except.contains(&user_id)
+    }
+}
+
+/// An event together with its audience, as sent through Redis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub event: RealtimeEvent,
+    #[serde(default)]
+    pub audience: Audience,
+}
+
+impl RealtimeEvent {
+    pub fn notification(user_id: Uuid, kind: NotificationKind) -> RealtimeEvent {
+        RealtimeEvent::Notification {
+            user_id,
+            kind: kind.as_str().to_string(),
+        }
+    }
+
+    /// Whether this event ends a connection the user opened with a session
+    /// from `session_epoch`.
+    pub fn ends_session(&self, user_id: Uuid, session_epoch: i32) -> bool {
+        matches!(
+            self,
+            RealtimeEvent::SessionsEnded { user_id: ended, session_epoch: current }
+                if *ended == user_id && session_epoch < *current
+        )
+    }
+}
+
+/// Fans events out to the WebSocket connections of every server instance.
+/// Events are only delivered through Redis, so connections on the instance
+/// that published an event get it the same way as everyone else.
+pub struct Realtime {
+    publisher: RedisPool,
+    events: broadcast::Sender<Envelope>,
+}
+
+impl Realtime {
+    pub fn new(publisher: RedisPool) -> Realtime {
+        let (events, _) = broadcast::channel(1024);
+        Realtime { publisher, events }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Envelope> {
+        self.events.subscribe()
+    }
+
+    /// Publishes an event to everyone allowed by the handlers' own checks.
+    pub async fn publish(&self, event: RealtimeEvent) {
+        self.publish_to(event, Audience::default()).await
+    }
+
+    /// Failing to publish only costs live updates, so the error is logged
+    /// instead of failing the request that caused the event.
+    pub async fn publish_to(&self, event: RealtimeEvent, audience: Audience) {
+        let payload = match serde_json::to_string(&Envelope { event, audience }) {
+            Ok(payload) => payload,
+            Err(err) => {
+                tracing::error!("failed to serialize realtime event: {}", err);
+                return;
+            }
+        };
+        if let Err(err) = self
+            .publisher
+            .next()
+            .publish::<i64, _, _>(CHANNEL, payload)
+            .await
+        {
+            tracing::error!("failed to publish realtime event: {}", err);
+        }
+    }
+
+    /// Subscribes to the Redis channel and forwards its messages to the local
+    /// connections until the server shuts down.
+    pub async fn listen(&self, config: RedisConfig) -> Result<(), RedisError> {
+        let subscriber = Builder::from_config(config).build_subscriber_client()?;
+        subscriber.init().await?;
+        // resubscribes after reconnecting
+        subscriber.manage_subscriptions();
+        subscriber.subscribe(CHANNEL).await?;
+
+        let events = self.events.clone();
+        let mut messages = subscriber.message_rx();
+        tokio::spawn(async move {
+            // the client disconnects when dropped
+            let _subscriber = subscriber;
+            loop {
+                match messages.recv().await {
+                    Ok(message) => {
+                        let event = message
+                            .value
+                            .as_str()
+                            .and_then(|payload| serde_json::from_str(&payload).ok());
+                        match event {
+                            // no connected receivers is not an error
+                            Some(event) => {
+                                let _ = events.send(event);
+                            }
+                            None => tracing::warn!("ignoring malformed realtime event"),
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!("realtime listener skipped {} events", skipped)
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_ended_closes_older_connections_of_the_user() {
+        let user_id = Uuid::new_v4();
+        let event = RealtimeEvent::SessionsEnded {
+            user_id,
+            session_epoch: 2,
+        };
+        assert!(event.ends_session(user_id, 1));
+        // a session from the new epoch was started after the event
+        assert!(!event.ends_session(user_id, 2));
+        assert!(!event.ends_session(Uuid::new_v4(), 1));
+    }
+}
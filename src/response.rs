@@ -1,6 +1,6 @@
 use axum::async_trait;
 use axum::body::Body;
-use axum::extract::rejection::PathRejection;
+use axum::extract::rejection::{PathRejection, QueryRejection};
 use axum::extract::FromRequestParts;
 use axum::extract::{rejection::JsonRejection, FromRequest};
 use axum::http::request::Parts;
@@ -67,6 +67,8 @@ pub enum AppError {
     ValidationError(#[from] ValidationErrors),
     #[error("invalid path")]
     PathRejection(PathRejection),
+    #[error("invalid query")]
+    QueryRejection(QueryRejection),
     #[error("jsend fail")]
     JsendFail(Value),
     #[error("jsend error")]
@@ -102,6 +104,10 @@ impl IntoResponse for AppError {
                 StatusCode::OK,
                 JsendResponse::error("invalid path data".to_string()),
             ),
+            AppError::QueryRejection(_) => (
+                StatusCode::BAD_REQUEST,
+                JsendResponse::error("invalid query data".to_string()),
+            ),
             AppError::JsendError(message) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 JsendResponse::error(message),
@@ -155,6 +161,23 @@ where
     }
 }
 
+pub struct AppQuery<T>(pub T);
+#[async_trait]
+impl<S, T> FromRequestParts<S> for AppQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Query::<T>::from_request_parts(parts, state).await {
+            Ok(value) => Ok(Self(value.0)),
+            Err(rejection) => Err(AppError::QueryRejection(rejection)),
+        }
+    }
+}
+
 fn valiation_error_to_hashmap(err: ValidationErrors) -> Value {
     let mut error_map: HashMap<String, String> = HashMap::new();
 
@@ -164,7 +187,7 @@ fn valiation_error_to_hashmap(err: ValidationErrors) -> Value {
             .map(|e| {
                 e.message
                     .clone()
-                    .unwrap_or_else(|| Cow::Borrowed("invalid value"))
+                    .unwrap_or(Cow::Borrowed("invalid value"))
                     .into_owned()
             })
             .collect();
@@ -1,7 +1,9 @@
 use crate::{
     handlers::{
-        auth_handlers, comment_handlers, error_handlers, post_handlers, profile_handlers,
-        user_handlers,
+        account_handlers, auth_handlers, block_handlers, bookmark_handlers, comment_handlers,
+        community_handlers, conversation_handlers, error_handlers, follow_handlers,
+        mention_handlers, notification_handlers, oidc_handlers, passkey_handlers, post_handlers,
+        profile_handlers, realtime_handlers, search_handlers, tag_handlers, user_handlers,
     },
     session_auth::auth,
     AppState,
@@ -10,7 +12,7 @@ use axum::{
     http::StatusCode,
     middleware,
     response::IntoResponse,
-    routing::{delete, get, post},
+    routing::{delete, get, patch, post},
     Router,
 };
 use std::sync::Arc;
@@ -27,21 +29,79 @@ pub fn create_router(app_state: Arc<AppState>) -> Router {
     // Define the protected routes
     let protected_routes = Router::new()
         .route("/posts", post(post_handlers::create_post))
+        .route("/feed", get(post_handlers::get_feed))
         .route("/posts/:post_id", delete(post_handlers::delete_post))
         .route("/posts/:post_id/react", post(post_handlers::react_to_post))
+        .route("/posts/:post_id/publish", post(post_handlers::publish_post_handler))
+        .route("/posts/:post_id/pin", post(post_handlers::pin_post).delete(post_handlers::unpin_post))
+        .route("/posts/:post_id/repost", post(post_handlers::repost_post).delete(post_handlers::undo_repost))
+        .route("/posts/:post_id/bookmark", post(bookmark_handlers::bookmark_post).delete(bookmark_handlers::unbookmark_post))
         .route("/posts/:post_id/comments",post(comment_handlers::create_comment_handler))
+        .route("/posts/:post_id/comments/:comment_id", patch(comment_handlers::update_comment_handler).delete(comment_handlers::delete_comment_handler))
         .route("/auth/logout", post(auth_handlers::logout_handler))
         .route("/auth/status", post(auth_handlers::status_handler))
-        .route("/profile/upload",post(profile_handlers::upload_profile_pic));
+        .route("/auth/password", patch(auth_handlers::change_password_handler))
+        .route("/me", delete(account_handlers::delete_account_handler))
+        .route("/me/export", post(account_handlers::request_export_handler))
+        .route("/me/exports", get(account_handlers::get_exports_handler))
+        .route("/profile/upload",post(profile_handlers::upload_profile_pic))
+        .route("/profile", patch(profile_handlers::update_profile))
+        .route("/user/:username/follow", post(follow_handlers::follow_user).delete(follow_handlers::unfollow_user))
+        .route("/user/:username/block", post(block_handlers::block_user).delete(block_handlers::unblock_user))
+        .route("/user/:username/mute", post(block_handlers::mute_user).delete(block_handlers::unmute_user))
+        .route("/me/drafts", get(post_handlers::get_drafts))
+        .route("/me/bookmarks", get(bookmark_handlers::get_bookmarks))
+        .route("/me/mentions", get(mention_handlers::get_mentions))
+        .route("/ws", get(realtime_handlers::realtime_handler))
+        .route("/conversations", get(conversation_handlers::get_conversations).post(conversation_handlers::create_conversation))
+        .route("/conversations/:conversation_id", delete(conversation_handlers::leave_conversation))
+        .route("/conversations/:conversation_id/messages", get(conversation_handlers::get_messages).post(conversation_handlers::send_message))
+        .route("/conversations/:conversation_id/read", post(conversation_handlers::mark_conversation_read))
+        .route("/conversations/:conversation_id/stream", get(conversation_handlers::stream_messages))
+        .route("/communities", post(community_handlers::create_community))
+        .route("/c/:name", patch(community_handlers::update_community))
+        .route("/c/:name/join", post(community_handlers::join_community).delete(community_handlers::leave_community))
+        .route("/c/:name/moderators/:username", post(community_handlers::add_moderator).delete(community_handlers::remove_moderator))
+        .route("/c/:name/bans/:username", post(community_handlers::ban_member).delete(community_handlers::unban_member))
+        .route("/notifications", get(notification_handlers::get_notifications))
+        .route("/notifications/read", post(notification_handlers::mark_all_notifications_read))
+        .route("/notifications/:notification_id/read", post(notification_handlers::mark_notification_read))
+        .route("/me/follow-requests", get(follow_handlers::get_follow_requests))
+        .route("/me/follow-requests/:username", post(follow_handlers::approve_follow_request).delete(follow_handlers::deny_follow_request))
+        .route("/me/blocks", get(block_handlers::get_blocked_users))
+        .route("/me/mutes", get(block_handlers::get_muted_users))
+        .route("/auth/passkeys", get(passkey_handlers::get_passkeys_handler))
+        .route("/auth/passkeys/:passkey_id", delete(passkey_handlers::delete_passkey_handler))
+        .route("/auth/passkey/register/start", post(passkey_handlers::passkey_register_start_handler))
+        .route("/auth/passkey/register/finish", post(passkey_handlers::passkey_register_finish_handler));
 
     // Define the unprotected routes
     let unprotected_routes = Router::new()
         .route("/users", get(user_handlers::get_all_users))
+        .route("/search", get(search_handlers::search_handler))
+        .route("/communities", get(community_handlers::get_communities))
+        .route("/c/:name", get(community_handlers::get_community))
+        .route("/c/:name/posts", get(community_handlers::get_community_posts))
+        .route("/c/:name/members", get(community_handlers::get_community_members))
+        .route("/tags/trending", get(tag_handlers::get_trending_tags))
+        .route("/tags/:tag/posts", get(tag_handlers::get_tag_posts))
         .route("/user/:username", get(profile_handlers::get_profile))
+        .route("/user/:username/posts", get(post_handlers::get_user_posts))
+        .route("/user/:username/comments", get(comment_handlers::get_user_comments_handler))
+        .route("/user/:username/followers", get(follow_handlers::get_followers))
+        .route("/user/:username/following", get(follow_handlers::get_following))
         .route("/auth/login", post(auth_handlers::login_handler))
         .route("/auth/register", post(auth_handlers::register_handler))
+        .route("/auth/password/forgot", post(auth_handlers::forgot_password_handler))
+        .route("/auth/password/reset", post(auth_handlers::reset_password_handler))
+        .route("/auth/oidc/login", get(oidc_handlers::oidc_login_handler))
+        .route("/auth/oidc/callback", get(oidc_handlers::oidc_callback_handler))
+        .route("/auth/passkey/login/start", post(passkey_handlers::passkey_login_start_handler))
+        .route("/auth/passkey/login/finish", post(passkey_handlers::passkey_login_finish_handler))
+        .route("/exports/:token", get(account_handlers::download_export_handler))
         .route("/posts", get(post_handlers::get_all_posts))
-        .route("/posts/:post_id/comments",get(comment_handlers::get_comments_handler)).fallback(handle_invalid_path)
+        .route("/posts/:post_id/comments",get(comment_handlers::get_comments_handler))
+        .route("/posts/:post_id/comments/stream", get(comment_handlers::stream_comments_handler)).fallback(handle_invalid_path)
         .route("/posts/:post_id", get(post_handlers::get_post));
 
     // Apply the middleware layer to protected routes
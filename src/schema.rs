@@ -1,9 +1,14 @@
 use crate::validation::{
-    validate_content_length, validate_email_length, validate_password_length,
-    validate_title_length, validate_username_length,
+    validate_bio_length, validate_community_description_length, validate_community_name,
+    validate_content_length, validate_display_name_length, validate_email_length,
+    validate_location_length, validate_message_length, validate_pronouns_length, validate_tags,
+    validate_title_length, validate_username_length, validate_website,
 };
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
+use uuid::Uuid;
 use validator::Validate;
+use webauthn_rs::prelude::RegisterPublicKeyCredential;
 
 #[derive(Debug, Deserialize, Validate)]
 pub struct RegisterUserSchema {
@@ -13,8 +18,8 @@ pub struct RegisterUserSchema {
     #[serde(default)]
     #[validate(custom(function = "validate_email_length"))]
     pub email: String,
+    /// Checked by the password policy.
     #[serde(default)]
-    #[validate(custom(function = "validate_password_length"))]
     pub password: String,
 }
 
@@ -24,10 +29,58 @@ pub struct LoginUserSchema {
     #[validate(custom(function = "validate_username_length"))]
     pub username: String,
     #[serde(default)]
-    #[validate(custom(function = "validate_password_length"))]
+    #[validate(length(min = 1, message = "password cannot be empty"))]
     pub password: String,
 }
 
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChangePasswordSchema {
+    #[serde(default)]
+    pub current_password: String,
+    /// Checked by the password policy.
+    #[serde(default)]
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ForgotPasswordSchema {
+    #[serde(default)]
+    #[validate(custom(function = "validate_email_length"))]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResetPasswordSchema {
+    #[serde(default)]
+    #[validate(length(min = 1, message = "token cannot be empty"))]
+    pub token: String,
+    /// Checked by the password policy.
+    #[serde(default)]
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct DeleteAccountSchema {
+    #[serde(default)]
+    pub password: String,
+}
+
+/// Fields left out of the request keep their current value.
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateProfileSchema {
+    #[validate(custom(function = "validate_bio_length"))]
+    pub bio: Option<String>,
+    #[validate(custom(function = "validate_display_name_length"))]
+    pub display_name: Option<String>,
+    #[validate(custom(function = "validate_website"))]
+    pub website: Option<String>,
+    #[validate(custom(function = "validate_location_length"))]
+    pub location: Option<String>,
+    #[validate(custom(function = "validate_pronouns_length"))]
+    pub pronouns: Option<String>,
+    pub is_private: Option<bool>,
+}
+
 #[derive(Debug, Deserialize, Validate)]
 pub struct PostSchema {
     #[serde(default)]
@@ -36,6 +89,21 @@ pub struct PostSchema {
     #[serde(default)]
     #[validate(custom(function = "validate_content_length"))]
     pub content: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_tags"))]
+    pub tags: Vec<String>,
+    pub community_id: Option<Uuid>,
+    pub quote_of_id: Option<Uuid>,
+    /// Keeps the post as a draft instead of publishing it.
+    #[serde(default)]
+    pub draft: bool,
+    /// Schedules the post instead of publishing it right away.
+    pub publish_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PublishSchema {
+    pub publish_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -50,3 +118,133 @@ pub struct ReactPostSchema {
     #[serde(default)]
     pub is_like: bool,
 }
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasskeyRegisterSchema {
+    #[serde(default)]
+    #[validate(length(max = 50, message = "name too long"))]
+    pub name: String,
+    pub credential: RegisterPublicKeyCredential,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasskeyLoginSchema {
+    #[serde(default)]
+    #[validate(custom(function = "validate_username_length"))]
+    pub username: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct FeedSchema {
+    /// Mix in popular posts when the caller follows only a few accounts.
+    #[serde(default)]
+    pub popular: bool,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UserSort {
+    #[default]
+    Joined,
+    Activity,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UserSearchSchema {
+    #[validate(length(max = 20, message = "search too long"))]
+    pub q: Option<String>,
+    #[serde(default)]
+    pub sort: UserSort,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct SearchSchema {
+    #[serde(default)]
+    #[validate(length(min = 1, max = 100, message = "search must be between 1 and 100 characters"))]
+    pub q: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateConversationSchema {
+    #[serde(default)]
+    #[validate(length(min = 1, max = 9, message = "conversations have between 2 and 10 members"))]
+    pub usernames: Vec<String>,
+    #[validate(length(max = 50, message = "title too long"))]
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct MessageSchema {
+    #[serde(default)]
+    #[validate(custom(function = "validate_message_length"))]
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CommunitySchema {
+    #[serde(default)]
+    #[validate(custom(function = "validate_community_name"))]
+    pub name: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_community_description_length"))]
+    pub description: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateCommunitySchema {
+    #[validate(custom(function = "validate_community_description_length"))]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct PaginationSchema {
+    #[serde(default = "default_page")]
+    #[validate(range(min = 1, max = 10000, message = "page must be between 1 and 10000"))]
+    pub page: i64,
+    #[serde(default = "default_per_page")]
+    #[validate(range(min = 1, max = 100, message = "per_page must be between 1 and 100"))]
+    pub per_page: i64,
+}
+
+impl PaginationSchema {
+    pub fn offset(&self) -> i64 {
+        // saturating so that an unvalidated page can never overflow
+        self.page.saturating_sub(1).saturating_mul(self.per_page)
+    }
+}
+
+fn default_page() -> i64 {
+    1
+}
+
+fn default_per_page() -> i64 {
+    20
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_skips_the_previous_pages() {
+        let pagination = PaginationSchema {
+            page: 3,
+            per_page: 20,
+        };
+        assert_eq!(pagination.offset(), 40);
+    }
+
+    #[test]
+    fn offset_saturates_instead_of_overflowing() {
+        let pagination = PaginationSchema {
+            page: i64::MAX,
+            per_page: 100,
+        };
+        assert_eq!(pagination.offset(), i64::MAX);
+        let pagination = PaginationSchema {
+            page: i64::MIN,
+            per_page: 100,
+        };
+        assert_eq!(pagination.offset(), i64::MIN);
+    }
+}
@@ -11,28 +11,67 @@ use std::sync::Arc;
 use tower_sessions::Session;
 use uuid::Uuid;
 
+/// Session key holding the users session epoch at the time of login.
+pub const SESSION_EPOCH: &str = "session_epoch";
+
 pub async fn auth(
     session: Session,
     State(data): State<Arc<AppState>>,
     mut req: Request<Body>,
     next: Next,
 ) -> Result<impl IntoResponse, AppError> {
-    if let Some(user_id) = session
+    let user = session_user(&session, &data).await?;
+
+    let user =
+        user.ok_or_else(|| AppError::JsendFail(json!({"authentication".to_string() : "user is not authenticated".to_string()})))?;
+
+    req.extensions_mut().insert(user);
+    Ok(next.run(req).await)
+}
+
+/// The logged in user for routes that are also open to anonymous visitors.
+/// Sessions the auth middleware would reject count as anonymous.
+pub async fn current_user_id(session: &Session, data: &AppState) -> Result<Option<Uuid>, AppError> {
+    Ok(session_user(session, data).await?.and_then(|user| user.id))
+}
+
+/// Whether sessions from `session_epoch` have ended since a connection was
+/// opened with one. Checked once a connection listens for `SessionsEnded`, so
+/// an epoch bump in between is not missed.
+pub async fn session_ended(data: &AppState, user_id: Uuid, session_epoch: i32) -> Result<bool, AppError> {
+    let current = sqlx::query_scalar!("SELECT session_epoch FROM users WHERE id = $1", user_id)
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+    Ok(current != Some(session_epoch))
+}
+
+/// The user a session belongs to, None for anonymous and ended sessions.
+pub async fn session_user(session: &Session, data: &AppState) -> Result<Option<UserModel>, AppError> {
+    let Some(user_id) = session
         .get::<Uuid>("user_id")
         .await
         .map_err(|_| AppError::InternalServerError)?
-    {
-        let user = sqlx::query_as!(UserModel, "SELECT * FROM users WHERE id = $1", user_id)
-            .fetch_optional(&data.db)
-            .await
-            .map_err(|_| AppError::InternalServerError)?;
-
-        let user =
-            user.ok_or_else(|| AppError::JsendFail(json!({"authentication".to_string() : "user is not authenticated".to_string()})))?;
-
-        req.extensions_mut().insert(user);
-        Ok(next.run(req).await)
-    } else {
-        Err(AppError::JsendFail(json!( {"authentication".to_string() : "user is not authenticated".to_string()} )))
-    }
+    else {
+        return Ok(None);
+    };
+    let epoch = session
+        .get::<i32>(SESSION_EPOCH)
+        .await
+        .map_err(|_| AppError::InternalServerError)?
+        .unwrap_or(0);
+
+    // requesting account deletion bumps the epoch, ending every session even
+    // once a new login cancels the deletion, suspended accounts are locked out
+    // until a moderator lifts the suspension
+    sqlx::query_as!(
+        UserModel,
+        "SELECT * FROM users WHERE id = $1 AND session_epoch = $2 AND suspended_at IS NULL
+        AND NOT EXISTS (SELECT 1 FROM account_deletions WHERE account_deletions.user_id = users.id)",
+        user_id,
+        epoch
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|_| AppError::InternalServerError)
 }
@@ -0,0 +1,120 @@
+use crate::response::AppError;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+pub const MAX_TAG_LENGTH: usize = 30;
+pub const MAX_TAGS_PER_POST: usize = 10;
+
+pub fn is_valid_tag(tag: &str) -> bool {
+    let len = tag.chars().count();
+    (1..=MAX_TAG_LENGTH).contains(&len) && tag.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Whether a `#` after this character starts a tag. Tags start the content or
+/// follow whitespace or punctuation, but not a word as in `C#` or the URL
+/// characters around a fragment as in `example.com/#section`.
+fn starts_tag_after(previous: Option<char>) -> bool {
+    match previous {
+        None => true,
+        Some(c) if c.is_whitespace() => true,
+        Some(c) => c.is_ascii_punctuation() && !"#&+-./:=?@_~%".contains(c),
+    }
+}
+
+/// Collects the `#tags` written in the content together with the explicit ones,
+/// lowercased and without duplicates, in order of appearance.
+pub fn collect_tags(content: &str, explicit: &[String]) -> Vec<String> {
+    let parsed = content.match_indices('#').filter_map(|(index, _)| {
+        if !starts_tag_after(content[..index].chars().last()) {
+            return None;
+        }
+        let tag: String = content[index + 1..]
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_')
+            .collect();
+        is_valid_tag(&tag).then_some(tag)
+    });
+    let explicit = explicit
+        .iter()
+        .map(|tag| tag.trim_start_matches('#').to_string());
+
+    let mut tags: Vec<String> = Vec::new();
+    for tag in explicit.chain(parsed).map(|tag| tag.to_lowercase()) {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags.truncate(MAX_TAGS_PER_POST);
+    tags
+}
+
+/// Replaces the tags of a post, creating tags that do not exist yet.
+pub async fn save_post_tags(
+    tx: &mut Transaction<'_, Postgres>,
+    post_id: Uuid,
+    tags: &[String],
+) -> Result<(), AppError> {
+    sqlx::query!("DELETE FROM post_tags WHERE post_id = $1", post_id)
+        .execute(&mut **tx)
+        .await
+        .map_err(|_| AppError::InternalServerError)?;
+
+    sqlx::query!(
+        "INSERT INTO tags (name) SELECT * FROM UNNEST($1::text[]) ON CONFLICT (name) DO NOTHING",
+        tags
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+
+    sqlx::query!(
+        "INSERT INTO post_tags (post_id, tag_id) SELECT $1, id FROM tags WHERE name = ANY($2)",
+        post_id,
+        tags
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(|_| AppError::InternalServerError)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_start_after_whitespace_or_punctuation() {
+        assert!(starts_tag_after(None));
+        assert!(starts_tag_after(Some(' ')));
+        assert!(starts_tag_after(Some('(')));
+        assert!(!starts_tag_after(Some('C')));
+        assert!(!starts_tag_after(Some('/')));
+        assert!(!starts_tag_after(Some('&')));
+    }
+
+    #[test]
+    fn collect_tags_parses_the_content() {
+        assert_eq!(
+            collect_tags("#Rust is fun (#async) #rust", &[]),
+            ["rust", "async"]
+        );
+        assert!(collect_tags("C# and example.com/#section and &#39;", &[]).is_empty());
+    }
+
+    #[test]
+    fn collect_tags_puts_explicit_tags_first() {
+        let explicit = vec!["#Web".to_string(), "rust".to_string()];
+        assert_eq!(
+            collect_tags("#rust #axum", &explicit),
+            ["web", "rust", "axum"]
+        );
+    }
+
+    #[test]
+    fn collect_tags_drops_invalid_and_extra_tags() {
+        let long = format!("#{}", "a".repeat(MAX_TAG_LENGTH + 1));
+        assert!(collect_tags(&long, &[]).is_empty());
+        let many: String = (0..15).map(|i| format!("#tag{} ", i)).collect();
+        assert_eq!(collect_tags(&many, &[]).len(), MAX_TAGS_PER_POST);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::tags::{is_valid_tag, MAX_TAGS_PER_POST};
 use std::{borrow::Cow, collections::HashMap};
 use validator::ValidationError;
 
@@ -25,18 +26,6 @@ pub fn validate_email_length(username: &str) -> Result<(), ValidationError> {
     )
 }
 
-pub fn validate_password_length(username: &str) -> Result<(), ValidationError> {
-    let len = username.len();
-    validate_length(
-        len,
-        8,
-        40,
-        "password too short",
-        "password too long",
-        "password cannot be empty",
-    )
-}
-
 pub fn validate_title_length(username: &str) -> Result<(), ValidationError> {
     let len = username.len();
     validate_length(
@@ -61,6 +50,101 @@ pub fn validate_content_length(username: &str) -> Result<(), ValidationError> {
     )
 }
 
+pub fn validate_message_length(content: &str) -> Result<(), ValidationError> {
+    validate_length(
+        content.chars().count(),
+        1,
+        1000,
+        "message too short",
+        "message too long",
+        "message cannot be empty",
+    )
+}
+
+pub fn validate_community_name(name: &str) -> Result<(), ValidationError> {
+    validate_length(
+        name.chars().count(),
+        3,
+        30,
+        "name too short",
+        "name too long",
+        "name cannot be empty",
+    )?;
+    if name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        Ok(())
+    } else {
+        Err(validation_error(
+            "name may only contain letters, digits and underscores",
+        ))
+    }
+}
+
+pub fn validate_community_description_length(description: &str) -> Result<(), ValidationError> {
+    validate_max_length(description.chars().count(), 500, "description too long")
+}
+
+pub fn validate_bio_length(bio: &str) -> Result<(), ValidationError> {
+    validate_max_length(bio.chars().count(), 300, "bio too long")
+}
+
+pub fn validate_display_name_length(display_name: &str) -> Result<(), ValidationError> {
+    validate_max_length(display_name.chars().count(), 50, "display name too long")
+}
+
+pub fn validate_location_length(location: &str) -> Result<(), ValidationError> {
+    validate_max_length(location.chars().count(), 50, "location too long")
+}
+
+pub fn validate_pronouns_length(pronouns: &str) -> Result<(), ValidationError> {
+    validate_max_length(pronouns.chars().count(), 30, "pronouns too long")
+}
+
+pub fn validate_website(website: &str) -> Result<(), ValidationError> {
+    validate_max_length(website.len(), 100, "website too long")?;
+    // an empty website clears the field
+    if website.is_empty() || website.starts_with("https://") || website.starts_with("http://") {
+        Ok(())
+    } else {
+        Err(validation_error(
+            "website must start with http:// or https://",
+        ))
+    }
+}
+
+pub fn validate_tags(tags: &[String]) -> Result<(), ValidationError> {
+    validate_max_length(tags.len(), MAX_TAGS_PER_POST, "too many tags")?;
+    if tags
+        .iter()
+        .all(|tag| is_valid_tag(tag.trim_start_matches('#')))
+    {
+        Ok(())
+    } else {
+        Err(validation_error(
+            "tags may only contain letters, digits and underscores",
+        ))
+    }
+}
+
+fn validate_max_length(
+    len: usize,
+    max: usize,
+    max_err: &'static str,
+) -> Result<(), ValidationError> {
+    if len > max {
+        Err(validation_error(max_err))
+    } else {
+        Ok(())
+    }
+}
+
+fn validation_error(message: &'static str) -> ValidationError {
+    ValidationError {
+        code: Cow::Borrowed(message),
+        message: Some(Cow::Borrowed(message)),
+        params: HashMap::new(),
+    }
+}
+
 fn validate_length(
     len: usize,
     min: usize,
@@ -69,7 +153,7 @@ fn validate_length(
     max_err: &'static str,
     empty_err: &'static str,
 ) -> Result<(), ValidationError> {
-    if len == 0 || len == 1 {
+    if len == 0 {
         let error = ValidationError {
             code: Cow::Borrowed(empty_err),
             message: Some(Cow::Borrowed(empty_err)),